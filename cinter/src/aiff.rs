@@ -0,0 +1,110 @@
+use crate::sample::{find_chunk, read_chunks, read_text, write_chunk, SampleFile, SAMPLE_RATE};
+
+// 80-bit IEEE extended float, as used for the AIFF sample rate.
+fn extended(value: u32) -> [u8; 10] {
+	let mut bytes = [0; 10];
+	if value > 0 {
+		let shift = value.leading_zeros();
+		bytes[0..2].copy_from_slice(&(16383 + 31 - shift as u16).to_be_bytes());
+		bytes[2..6].copy_from_slice(&(value << shift).to_be_bytes());
+	}
+	bytes
+}
+
+/// Mono AIFF file with 8 or 16 bits per sample, with the loop in MARK
+/// and INST chunks and the name in a NAME chunk.
+pub fn write_sample(sample: &SampleFile, bits: u16) -> Vec<u8> {
+	assert!(bits == 8 || bits == 16);
+	let mut body = b"AIFF".to_vec();
+
+	let mut comm = vec![];
+	comm.extend_from_slice(&1u16.to_be_bytes()); // Channels
+	comm.extend_from_slice(&(sample.data.len() as u32).to_be_bytes());
+	comm.extend_from_slice(&bits.to_be_bytes());
+	comm.extend_from_slice(&extended(SAMPLE_RATE));
+	write_chunk(&mut body, b"COMM", true, &comm);
+
+	if let Some(start) = sample.repeat_start() {
+		// Markers 1 and 2 at the loop start and end, with empty names
+		let mut mark = 2u16.to_be_bytes().to_vec();
+		for (id, position) in [(1u16, start), (2, sample.data.len())] {
+			mark.extend_from_slice(&id.to_be_bytes());
+			mark.extend_from_slice(&(position as u32).to_be_bytes());
+			mark.extend_from_slice(&[0, 0]);
+		}
+		write_chunk(&mut body, b"MARK", true, &mark);
+
+		let mut inst = vec![60, 0, 0, 127, 1, 127]; // Base note, detune, note and velocity ranges
+		inst.extend_from_slice(&0i16.to_be_bytes()); // Gain
+		for word in [1u16, 1, 2, 0, 0, 0] { // Forward sustain loop, no release loop
+			inst.extend_from_slice(&word.to_be_bytes());
+		}
+		write_chunk(&mut body, b"INST", true, &inst);
+	}
+
+	if let Some(name) = &sample.name {
+		write_chunk(&mut body, b"NAME", true, name.as_bytes());
+	}
+
+	let mut ssnd = vec![0; 8]; // Offset and block size
+	match bits {
+		8 => ssnd.extend(sample.data.iter().map(|&s| s as u8)),
+		_ => ssnd.extend(sample.data.iter().flat_map(|&s| ((s as i16) << 8).to_be_bytes())),
+	}
+	write_chunk(&mut body, b"SSND", true, &ssnd);
+
+	let mut aiff = vec![];
+	write_chunk(&mut aiff, b"FORM", true, &body);
+	aiff
+}
+
+/// Read a mono 8 or 16-bit AIFF file.
+pub fn read_sample(data: &[u8]) -> anyhow::Result<SampleFile> {
+	let form = read_chunks(data, true)?;
+	let body = match find_chunk(&form, b"FORM") {
+		Some([b'A', b'I', b'F', b'F', body @ ..]) => body,
+		_ => return Err(anyhow::anyhow!("Not an AIFF file")),
+	};
+	let chunks = read_chunks(body, true)?;
+	let word = |bytes: &[u8], i: usize| u16::from_be_bytes([bytes[i], bytes[i + 1]]);
+	let long = |bytes: &[u8], i: usize| u32::from_be_bytes(bytes[i .. i + 4].try_into().unwrap());
+
+	let comm = find_chunk(&chunks, b"COMM").ok_or_else(|| anyhow::anyhow!("Could not find COMM chunk"))?;
+	if comm.len() < 18 {
+		return Err(anyhow::anyhow!("COMM chunk truncated"));
+	}
+	let (channels, frames, bits) = (word(comm, 0), long(comm, 2) as usize, word(comm, 6));
+	if channels != 1 || (bits != 8 && bits != 16) {
+		return Err(anyhow::anyhow!("Only mono 8 or 16-bit samples are supported"));
+	}
+	let ssnd = find_chunk(&chunks, b"SSND").ok_or_else(|| anyhow::anyhow!("Could not find SSND chunk"))?;
+	let pcm = ssnd.get(4..8).and_then(|_| ssnd.get(8 + long(ssnd, 0) as usize ..))
+		.ok_or_else(|| anyhow::anyhow!("SSND chunk truncated"))?;
+	let data: Vec<i8> = match bits {
+		8 => pcm.iter().map(|&s| s as i8).take(frames).collect(),
+		_ => pcm.chunks_exact(2).map(|s| s[0] as i8).take(frames).collect(),
+	};
+
+	// Loop start from the sustain loop marker
+	let mut repeat_length = 0;
+	if let (Some(mark), Some(inst)) = (find_chunk(&chunks, b"MARK"), find_chunk(&chunks, b"INST")) {
+		if mark.len() >= 2 && inst.len() >= 14 && word(inst, 8) != 0 {
+			let begin = word(inst, 10);
+			let mut index = 2;
+			for _ in 0..word(mark, 0) {
+				if index + 7 > mark.len() {
+					break;
+				}
+				if word(mark, index) == begin {
+					repeat_length = data.len().saturating_sub(long(mark, index + 2) as usize);
+				}
+				// Marker name as a padded Pascal string
+				index += 6 + ((mark[index + 6] as usize + 2) & !1);
+			}
+		}
+	}
+
+	let name = find_chunk(&chunks, b"NAME").map(read_text);
+
+	Ok(SampleFile { name, data, repeat_length })
+}
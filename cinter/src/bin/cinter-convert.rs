@@ -0,0 +1,128 @@
+use std::fs;
+use std::path::Path;
+
+use cinter::convert::{convert_with_options, write_diagnostics_json, Diagnostic, DiagnosticCode, Options, Severity};
+use cinter::include::{symbol_name, Format, Sizes};
+use cinter::layout::{apply_layout, best_layout, compare_layouts};
+use cinter::module::Module;
+use cinter::noteids::{apply_exact_fixes, id_ranges, largest_ranges, suggestions};
+
+fn main() -> anyhow::Result<()> {
+	let args: Vec<String> = std::env::args().collect();
+	let json = args.iter().any(|a| a == "--json");
+	let strict = args.iter().any(|a| a == "--strict");
+	let fix_note_ids = args.iter().any(|a| a == "--fix-note-ids");
+	let layouts = args.iter().any(|a| a == "--layouts");
+	let options = Options {
+		cia_tempo: args.iter().any(|a| a == "--cia-tempo"),
+		trim: args.iter().any(|a| a == "--trim"),
+	};
+	let files: Vec<&String> = args[1..].iter().filter(|a| !a.starts_with("--")).collect();
+	let unknown = args[1..].iter().find(|a| a.starts_with("--") && !["--json", "--strict", "--cia-tempo", "--trim", "--fix-note-ids", "--layouts"].contains(&a.as_str()));
+	if unknown.is_some() || files.len() < 2 {
+		if let Some(flag) = unknown {
+			println!("Unknown option {}", flag);
+		}
+		println!("Usage: {} [--json] [--strict] [--cia-tempo] [--trim] [--fix-note-ids] [--layouts] <input module file> <output binary data file> [<output raw instrument file>]", args[0]);
+		println!("Output files ending in .s or .asm are written as assembler source, and .c as C source,");
+		println!("with a header (.i or .h) next to the music data giving the sizes needed to play it.");
+		std::process::exit(1);
+	}
+	let module_file = files[0];
+	let output_file = files[1];
+	let raw_inst_file = files.get(2);
+
+	if !json {
+		println!("Converting module file {}...", module_file);
+	}
+	let mut module = Module::read(&fs::read(module_file)?)?;
+	let mut conversion = convert_with_options(&module, &options);
+	let mut fixes = vec![];
+	if fix_note_ids {
+		fixes = apply_exact_fixes(&mut module, &id_ranges(&conversion)?);
+		if !fixes.is_empty() {
+			conversion = convert_with_options(&module, &options);
+		}
+	}
+	let playable = conversion.is_playable();
+	let mut layout_report = String::new();
+	if layouts && playable {
+		let scores = compare_layouts(&conversion)?;
+		let best = best_layout(&scores);
+		apply_layout(&mut conversion, &best)?;
+		layout_report += &format!("{:<55}  {:>6}  {:>6}\n", "Layout", "Size", "Packed");
+		for score in &scores {
+			let mark = if score.layout == best { " <- used" } else if !score.layout.is_playable() { " (not playable)" } else { "" };
+			layout_report += &format!("{}  {:6}  {:6}{}\n", score.layout, score.size, score.packed, mark);
+		}
+	}
+
+	let mut warnings = vec![];
+	if conversion.raw_inst_size > 0 && raw_inst_file.is_none() {
+		warnings.push(Diagnostic::new(Severity::Warning, DiagnosticCode::RawInstrumentsNotWritten,
+			"Raw instruments used, but no raw instrument output file specified!"));
+	}
+	if conversion.raw_inst_size == 0 && raw_inst_file.is_some() {
+		warnings.push(Diagnostic::new(Severity::Warning, DiagnosticCode::NoRawInstruments,
+			"Raw instrument output file specified, but no raw instruments used!"));
+	}
+
+	let mut report = String::new();
+	if json {
+		conversion.diagnostics.extend(warnings);
+		write_diagnostics_json(&conversion.diagnostics, &mut report)?;
+	} else {
+		conversion.write_report(&mut report)?;
+		report += &layout_report;
+		for fix in &fixes {
+			report += &format!("Applied: {} (saving {} note IDs)\n", fix.fix, fix.saved);
+		}
+		if conversion.note_id_count > 512 {
+			let ranges = id_ranges(&conversion)?;
+			report += "Largest note ranges:\n";
+			for range in largest_ranges(&ranges, 8) {
+				report += &format!("  {}\n", range);
+			}
+			for suggestion in suggestions(&module, &ranges) {
+				report += &format!("Suggestion: {} (saving {} note IDs)\n", suggestion.fix, suggestion.saved);
+			}
+		}
+		for warning in &warnings {
+			report += &format!("Warning: {}\n", warning);
+		}
+		conversion.diagnostics.extend(warnings);
+	}
+	if !playable && !json {
+		report += "The song data cannot be played, so no output files were written.\n";
+	}
+	print!("{}", report);
+
+	if playable {
+		let format = Format::from_path(Path::new(output_file));
+		let symbol = symbol_name(Path::new(output_file));
+		fs::write(output_file, format.write(&symbol, &conversion.song_data))?;
+		let mut arrays = vec![symbol.as_str()];
+		let raw_symbol = raw_inst_file.map(|file| symbol_name(Path::new(file)));
+		if let (Some(raw_inst_file), Some(raw_symbol)) = (raw_inst_file, &raw_symbol) {
+			let raw_format = Format::from_path(Path::new(raw_inst_file));
+			fs::write(raw_inst_file, raw_format.write(raw_symbol, &conversion.raw_data))?;
+			if raw_format == Format::C {
+				arrays.push(raw_symbol);
+			}
+		}
+		if let Some(extension) = format.header_extension() {
+			let sizes = Sizes::new(&conversion);
+			let header = match format {
+				Format::C => sizes.c_header(&symbol, &arrays),
+				_ => sizes.asm_header(&symbol),
+			};
+			fs::write(Path::new(output_file).with_extension(extension), header)?;
+		}
+	}
+
+	if !playable || (strict && !conversion.diagnostics.is_empty()) {
+		std::process::exit(1);
+	}
+
+	Ok(())
+}
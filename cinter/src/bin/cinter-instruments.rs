@@ -0,0 +1,63 @@
+use std::fs;
+
+use cinter::cost::{init_cost, Cpu};
+use cinter::engine::{CinterEngine, SynthVersion, WordParameters};
+use cinter::song::{write_instruments, InstrumentRecord};
+
+// Length in bytes, as shown by the sample editor, to length in words.
+fn parse_length(value: &str) -> anyhow::Result<u16> {
+	let bytes = value.parse::<u32>()?;
+	if bytes % 2 != 0 || bytes > 0x1FFFE {
+		return Err(anyhow::anyhow!("Invalid length {}", bytes));
+	}
+	Ok((bytes / 2) as u16)
+}
+
+fn parse_instrument(spec: &str) -> anyhow::Result<InstrumentRecord> {
+	let fields: Vec<&str> = spec.split(':').collect();
+	if fields.len() < 2 || fields.len() > 3 {
+		return Err(anyhow::anyhow!("Expected <name>:<length>[:<repeat length>]"));
+	}
+	let name = fields[0];
+	let length = parse_length(fields[1])?;
+	let replen = match fields.get(2) {
+		Some(replen) => parse_length(replen)?,
+		None => 0,
+	};
+	let params = if name.eq_ignore_ascii_case("raw") {
+		None
+	} else {
+		let params = CinterEngine::parameters_from_sample_filename(name)?;
+		Some(WordParameters::from_params(SynthVersion::from_sample_filename(name), &params))
+	};
+	Ok(InstrumentRecord { length, replen, params })
+}
+
+fn main() -> anyhow::Result<()> {
+	let args: Vec<String> = std::env::args().collect();
+	if args.len() < 3 {
+		println!("Usage: {} <output instrument file> <instrument>...", args[0]);
+		println!("Each instrument is <sample name>:<length>[:<repeat length>], with lengths in bytes.");
+		println!("Use the sample name raw for raw instruments, which must come first.");
+		std::process::exit(1);
+	}
+	let output_file = &args[1];
+
+	let mut instruments = vec![];
+	for spec in &args[2..] {
+		let instrument = parse_instrument(spec).map_err(|e| anyhow::anyhow!("{}: {}", spec, e))?;
+		instruments.push(instrument);
+	}
+	let data = write_instruments(&instruments)?;
+	fs::write(output_file, &data)?;
+	let raw_count = instruments.iter().filter(|inst| inst.params.is_none()).count();
+	println!("Wrote {} raw and {} generated instruments ({} bytes) to {}",
+		raw_count, instruments.len() - raw_count, data.len(), output_file);
+	println!("Instrument memory: {} bytes", init_cost(Cpu::M68000, &instruments).memory);
+	for cpu in Cpu::ALL {
+		let cost = init_cost(cpu, &instruments);
+		println!("Approx. precalc time on {}: {:.1} seconds", cpu.name(), cost.seconds(cpu));
+	}
+
+	Ok(())
+}
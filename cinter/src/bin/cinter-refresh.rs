@@ -0,0 +1,39 @@
+use std::fs;
+
+use cinter::module::Module;
+use cinter::refresh::{refresh, stale_instruments};
+
+fn main() -> anyhow::Result<()> {
+	let args: Vec<String> = std::env::args().collect();
+	let check = args.iter().any(|a| a == "--check");
+	let files: Vec<&String> = args[1..].iter().filter(|a| !a.starts_with("--")).collect();
+	let unknown = args[1..].iter().find(|a| a.starts_with("--") && !["--check"].contains(&a.as_str()));
+	if unknown.is_some() || files.len() != 1 {
+		if let Some(flag) = unknown {
+			println!("Unknown option {}", flag);
+		}
+		println!("Usage: {} [--check] <module file>", args[0]);
+		println!("Regenerates the sample data of all Cinter instruments in the module.");
+		println!("With --check, only reports instruments whose sample data differs.");
+		std::process::exit(1);
+	}
+	let module_file = files[0];
+
+	let mut module = Module::read(&fs::read(module_file)?)?;
+	let stale = if check { stale_instruments(&module) } else { refresh(&mut module) };
+	for s in &stale {
+		println!("Instrument {:02} ({}): {} bytes differ from byte {}, by up to {}",
+			s.number, s.name, s.count, s.offset, s.max_difference);
+	}
+	if check {
+		if !stale.is_empty() {
+			std::process::exit(1);
+		}
+		println!("All Cinter instruments match their names.");
+	} else {
+		fs::write(module_file, module.write())?;
+		println!("Regenerated {} instruments and wrote {}", stale.len(), module_file);
+	}
+
+	Ok(())
+}
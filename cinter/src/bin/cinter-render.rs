@@ -0,0 +1,30 @@
+use std::fs;
+
+use cinter::render::{render, VBLANK_RATE};
+use cinter::song::SongData;
+use cinter::wav;
+
+const SAMPLE_RATE: u32 = 44100;
+
+fn main() -> anyhow::Result<()> {
+	let args: Vec<String> = std::env::args().collect();
+	if args.len() < 3 || args.len() > 4 {
+		println!("Usage: {} <input binary data file> [<input raw instrument file>] <output wav file>", args[0]);
+		std::process::exit(1);
+	}
+	let song_file = &args[1];
+	let raw_inst_file = if args.len() == 4 { Some(&args[2]) } else { None };
+	let output_file = &args[args.len() - 1];
+
+	println!("Rendering song data file {}...", song_file);
+	let song = SongData::read(&fs::read(song_file)?)?;
+	let raw_data = match raw_inst_file {
+		Some(raw_inst_file) => fs::read(raw_inst_file)?,
+		None => vec![],
+	};
+	let samples = render(&song, &raw_data, song.length(), SAMPLE_RATE)?;
+	fs::write(output_file, wav::stereo16(SAMPLE_RATE, &samples))?;
+	println!("Wrote {:.1} seconds to {}", song.length() as f64 / VBLANK_RATE as f64, output_file);
+
+	Ok(())
+}
@@ -0,0 +1,37 @@
+use std::fs;
+use std::path::Path;
+
+use cinter::convert::{convert_with_options, Options};
+use cinter::include::{symbol_name, Format};
+use cinter::module::Module;
+use cinter::sync::SyncTable;
+
+fn main() -> anyhow::Result<()> {
+	let args: Vec<String> = std::env::args().collect();
+	let options = Options {
+		cia_tempo: args.iter().any(|a| a == "--cia-tempo"),
+		..Options::default()
+	};
+	let files: Vec<&String> = args[1..].iter().filter(|a| !a.starts_with("--")).collect();
+	let unknown = args[1..].iter().find(|a| a.starts_with("--") && !["--cia-tempo"].contains(&a.as_str()));
+	if unknown.is_some() || files.len() != 2 {
+		if let Some(flag) = unknown {
+			println!("Unknown option {}", flag);
+		}
+		println!("Usage: {} [--cia-tempo] <input module file> <output sync file>", args[0]);
+		println!("Writes the note triggers, row starts and 8xx markers of the converted song by vblank.");
+		println!("The output is assembler source if its extension is .s or .asm, C source for .c and binary otherwise.");
+		std::process::exit(1);
+	}
+	let output_file = files[1];
+
+	let module = Module::read(&fs::read(files[0])?)?;
+	let table = SyncTable::new(&convert_with_options(&module, &options))?;
+	match Format::from_path(Path::new(output_file)) {
+		Format::Asm => fs::write(output_file, table.write_asm())?,
+		format => fs::write(output_file, format.write(&symbol_name(Path::new(output_file)), &table.write_binary()))?,
+	}
+	println!("Wrote {} events over {} vblanks to {}", table.events.len(), table.length, output_file);
+
+	Ok(())
+}
@@ -0,0 +1,44 @@
+use std::fs;
+
+use cinter::convert::{convert_with_options, Options};
+use cinter::module::Module;
+use cinter::song::SongData;
+use cinter::verify::first_divergence_with_options;
+
+fn main() -> anyhow::Result<()> {
+	let args: Vec<String> = std::env::args().collect();
+	let options = Options {
+		cia_tempo: args.iter().any(|a| a == "--cia-tempo"),
+		..Options::default()
+	};
+	let files: Vec<&String> = args[1..].iter().filter(|a| !a.starts_with("--")).collect();
+	let unknown = args[1..].iter().find(|a| a.starts_with("--") && !["--cia-tempo"].contains(&a.as_str()));
+	if unknown.is_some() || files.is_empty() || files.len() > 2 {
+		if let Some(flag) = unknown {
+			println!("Unknown option {}", flag);
+		}
+		println!("Usage: {} [--cia-tempo] <input module file> [<input binary data file>]", args[0]);
+		println!("With --cia-tempo, the song data is compared as converted with the CIA tempo option.");
+		std::process::exit(1);
+	}
+	let module_file = files[0];
+
+	println!("Comparing module file {} with Protracker 2.3d...", module_file);
+	let module = Module::read(&fs::read(module_file)?)?;
+	let conversion = convert_with_options(&module, &options);
+	let song_data = match files.get(1) {
+		Some(song_file) => fs::read(song_file)?,
+		None => conversion.song_data.clone(),
+	};
+	let song = SongData::read(&song_data)?;
+
+	match first_divergence_with_options(&module, &song, &conversion.instrument_numbers(), &options)? {
+		None => println!("No divergence."),
+		Some(divergence) => {
+			println!("{}", divergence);
+			std::process::exit(1);
+		},
+	}
+
+	Ok(())
+}
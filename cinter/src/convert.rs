@@ -0,0 +1,1306 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+use crate::cost::{instrument_cost, Cpu};
+use crate::engine::{CinterEngine, CinterParams, SynthVersion, WordParameters};
+use crate::module::{Module, TrackRow, CHANNEL_COUNT, INSTRUMENT_COUNT, ROW_COUNT};
+use crate::render::VBLANK_RATE;
+use crate::replay::wave_value;
+use crate::song::{InstrumentRecord, NoteRange, SongData};
+use crate::trim::played_lengths;
+
+pub const PERIOD_TABLE: [u16; 36] = [
+	856, 808, 762, 720, 678, 640, 604, 570, 538, 508, 480, 453,
+	428, 404, 381, 360, 339, 320, 302, 285, 269, 254, 240, 226,
+	214, 202, 190, 180, 170, 160, 151, 143, 135, 127, 120, 113
+];
+
+const VOLUME_SHIFT: u32 = 9;
+const NOTE_SHIFT: u32 = 0;
+const NOTE_ABS_MASK: i32 = 0x80;
+
+pub fn notename(note: Option<i32>) -> String {
+	match note {
+		None => "   ".to_string(),
+		Some(n) => {
+			let names = ["C-", "C#", "D-", "D#", "E-", "F-", "F#", "G-", "G#", "A-", "A#", "B-"];
+			format!("{}{}", names[n.rem_euclid(12) as usize], n.div_euclid(12) + 1)
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+	Error,
+	Warning,
+}
+
+impl Severity {
+	pub fn name(self) -> &'static str {
+		match self {
+			Severity::Error => "error",
+			Severity::Warning => "warning",
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DiagnosticCode {
+	UnsupportedCommand,
+	TempoSet,
+	BreakOutsidePattern,
+	InstrumentChangeOnToneportamento,
+	NoteWithNoInstrument,
+	NoPreviousOffset,
+	OffsetBeyondEnd,
+	OffsetCarriedOver,
+	FineslideOnNote,
+	ArpeggioWithNoBase,
+	ArpeggioInvalidBase,
+	ArpeggioNoteTooHigh,
+	PortamentoWithNoSource,
+	VibratoWithNoBase,
+	ToneportamentoWithNoSource,
+	ToneportamentoWithNoTarget,
+	ToneportamentoWithNoSpeed,
+	NonNotePeriod,
+	SlideOutOfRange,
+	UnsupportedSlide,
+	PhaseChangeOnRestart,
+	TooManyNoteIds,
+	EmptyInstrument,
+	RepeatNotAtEnd,
+	RawInstrumentsNotWritten,
+	NoRawInstruments,
+	UnplayableSongData,
+}
+
+impl DiagnosticCode {
+	/// Stable identifier for machine-readable output.
+	pub fn name(self) -> &'static str {
+		use DiagnosticCode::*;
+		match self {
+			UnsupportedCommand => "unsupported-command",
+			TempoSet => "tempo-set",
+			BreakOutsidePattern => "break-outside-pattern",
+			InstrumentChangeOnToneportamento => "instrument-change-on-toneportamento",
+			NoteWithNoInstrument => "note-with-no-instrument",
+			NoPreviousOffset => "no-previous-offset",
+			OffsetBeyondEnd => "offset-beyond-end",
+			OffsetCarriedOver => "offset-carried-over",
+			FineslideOnNote => "fineslide-on-note",
+			ArpeggioWithNoBase => "arpeggio-with-no-base",
+			ArpeggioInvalidBase => "arpeggio-invalid-base",
+			ArpeggioNoteTooHigh => "arpeggio-note-too-high",
+			PortamentoWithNoSource => "portamento-with-no-source",
+			VibratoWithNoBase => "vibrato-with-no-base",
+			ToneportamentoWithNoSource => "toneportamento-with-no-source",
+			ToneportamentoWithNoTarget => "toneportamento-with-no-target",
+			ToneportamentoWithNoSpeed => "toneportamento-with-no-speed",
+			NonNotePeriod => "non-note-period",
+			SlideOutOfRange => "slide-out-of-range",
+			UnsupportedSlide => "unsupported-slide",
+			PhaseChangeOnRestart => "phase-change-on-restart",
+			TooManyNoteIds => "too-many-note-ids",
+			EmptyInstrument => "empty-instrument",
+			RepeatNotAtEnd => "repeat-not-at-end",
+			RawInstrumentsNotWritten => "raw-instruments-not-written",
+			NoRawInstruments => "no-raw-instruments",
+			UnplayableSongData => "unplayable-song-data",
+		}
+	}
+
+	pub fn suggestion(self) -> Option<&'static str> {
+		use DiagnosticCode::*;
+		Some(match self {
+			TempoSet => "Use speed (F01-F1F) instead of tempo, or convert with CIA tempo",
+			BreakOutsidePattern => "Use a break row of at most 63",
+			InstrumentChangeOnToneportamento => "Remove the instrument number or the toneportamento",
+			NoteWithNoInstrument => "Add an instrument number",
+			NoPreviousOffset => "Give the sampleoffset an argument",
+			OffsetBeyondEnd => "Lower the sampleoffset argument",
+			OffsetCarriedOver => "Give the note an instrument number",
+			FineslideOnNote => "Move the fineslide to the next row",
+			ArpeggioWithNoBase => "Play a note before the arpeggio",
+			ArpeggioInvalidBase => "Play a new note before the arpeggio",
+			ArpeggioNoteTooHigh => "Lower the arpeggio note offsets",
+			PortamentoWithNoSource => "Play a note before the portamento",
+			VibratoWithNoBase => "Play a note before the vibrato",
+			ToneportamentoWithNoSource => "Play a note before the toneportamento",
+			ToneportamentoWithNoTarget => "Give the toneportamento a note",
+			ToneportamentoWithNoSpeed => "Give the toneportamento an argument",
+			UnsupportedSlide => "Use slide values 00-3F",
+			PhaseChangeOnRestart => "Retrigger the vibrato or tremolo with a note at the restart position",
+			TooManyNoteIds => "Reduce the note range or the number of sampleoffsets",
+			RepeatNotAtEnd => "Place the repeat at the end of the sample",
+			RawInstrumentsNotWritten => "Specify a raw instrument output file",
+			NoRawInstruments => "Leave out the raw instrument output file",
+			_ => return None,
+		})
+	}
+
+	/// Whether the problem leaves song data the player cannot play at all.
+	pub fn is_fatal(self) -> bool {
+		matches!(self, DiagnosticCode::TooManyNoteIds | DiagnosticCode::UnplayableSongData)
+	}
+}
+
+/// Place in the module where a problem was found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Location {
+	pub position: usize,
+	pub pattern: usize,
+	pub track: usize,
+	pub row: usize,
+}
+
+pub struct Diagnostic {
+	pub severity: Severity,
+	pub code: DiagnosticCode,
+	pub message: String,
+	pub location: Option<Location>,
+	pub instrument: Option<usize>,
+	pub suggestion: Option<&'static str>,
+}
+
+impl Diagnostic {
+	pub fn new(severity: Severity, code: DiagnosticCode, message: impl Into<String>) -> Diagnostic {
+		Diagnostic {
+			severity,
+			code,
+			message: message.into(),
+			location: None,
+			instrument: None,
+			suggestion: code.suggestion(),
+		}
+	}
+}
+
+impl fmt::Display for Diagnostic {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if let Some(instrument) = self.instrument {
+			write!(f, "Instrument {:02}: ", instrument)?;
+		}
+		write!(f, "{}", self.message)?;
+		if let Some(l) = &self.location {
+			write!(f, " in pattern {} track {} row {}", l.pattern, l.track, l.row)?;
+		}
+		Ok(())
+	}
+}
+
+fn json_string(s: &str) -> String {
+	let mut json = String::from("\"");
+	for c in s.chars() {
+		match c {
+			'"' => json.push_str("\\\""),
+			'\\' => json.push_str("\\\\"),
+			c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+			c => json.push(c),
+		}
+	}
+	json.push('"');
+	json
+}
+
+/// Write diagnostics as a JSON array, one object per line.
+pub fn write_diagnostics_json(diagnostics: &[Diagnostic], out: &mut impl fmt::Write) -> fmt::Result {
+	let optional = |v: Option<usize>| v.map_or("null".to_string(), |v| v.to_string());
+	writeln!(out, "[")?;
+	for (i, d) in diagnostics.iter().enumerate() {
+		write!(out, "  {{\"severity\": \"{}\", \"code\": \"{}\", \"message\": {}",
+			d.severity.name(), d.code.name(), json_string(&d.message))?;
+		let l = d.location;
+		write!(out, ", \"position\": {}, \"pattern\": {}, \"track\": {}, \"row\": {}",
+			optional(l.map(|l| l.position)), optional(l.map(|l| l.pattern)),
+			optional(l.map(|l| l.track)), optional(l.map(|l| l.row)))?;
+		write!(out, ", \"instrument\": {}, \"suggestion\": {}}}",
+			optional(d.instrument), d.suggestion.map_or("null".to_string(), json_string))?;
+		writeln!(out, "{}", if i + 1 < diagnostics.len() { "," } else { "" })?;
+	}
+	writeln!(out, "]")
+}
+
+pub struct InstrumentUsage {
+	pub is_cinter: bool,
+	pub length: usize,
+	pub replen: usize,
+	pub index: usize,
+	pub count: usize,
+	pub min_note: i32,
+	pub max_note: i32,
+	pub offsets: usize,
+	pub note_ids: usize,
+	/// Length in words as far as the song plays the instrument.
+	pub played_length: usize,
+	pub message: String,
+}
+
+pub struct InstrumentReport {
+	pub number: usize,
+	pub name: String,
+	/// None for instruments without a name.
+	pub version: Option<SynthVersion>,
+	/// None for instruments not used by the song.
+	pub usage: Option<InstrumentUsage>,
+}
+
+pub struct Conversion {
+	pub song_data: Vec<u8>,
+	pub raw_data: Vec<u8>,
+
+	pub diagnostics: Vec<Diagnostic>,
+	pub instruments: Vec<InstrumentReport>,
+
+	pub raw_inst_size: usize,
+	pub total_inst_size: usize,
+	pub total_inst_time: f64,
+	pub music_length: usize,
+	pub restart: usize,
+	pub note_id_count: usize,
+	pub data_word_count: usize,
+	/// Largest difference between the time of a tick in Protracker and the
+	/// vblank where it is played, in milliseconds. None if no tempo was set.
+	pub timing_deviation: Option<f64>,
+	/// Vblank and place in the module of each row played before the music loops.
+	pub rows: Vec<(usize, Location)>,
+	/// Vblank, track and argument of each 8xx command before the music loops.
+	pub markers: Vec<(usize, usize, u8)>,
+	/// Module channel played by each track of the song data.
+	pub channels: [usize; CHANNEL_COUNT],
+	/// Instrument memory in words and 68000 precalc seconds saved by trimming the
+	/// instruments to their played lengths, or which would be saved by it.
+	pub trim_size: usize,
+	pub trim_time: f64,
+	pub trimmed: bool,
+}
+
+#[derive(Clone, Default)]
+pub struct Options {
+	/// Play CIA tempos other than 125 by spreading the ticks over the vblanks.
+	pub cia_tempo: bool,
+	/// Shorten instruments to the part played by the song.
+	pub trim: bool,
+}
+
+impl Conversion {
+	/// Module instrument number for each instrument in the song data.
+	pub fn instrument_numbers(&self) -> Vec<usize> {
+		let used: Vec<(usize, usize)> = self.instruments.iter()
+			.filter_map(|inst| inst.usage.as_ref().map(|usage| (usage.index, inst.number)))
+			.collect();
+		let mut numbers = vec![0; used.len()];
+		for (index, number) in used {
+			numbers[index] = number;
+		}
+		numbers
+	}
+
+	pub fn is_playable(&self) -> bool {
+		!self.diagnostics.iter().any(|d| d.severity == Severity::Error && d.code.is_fatal())
+	}
+
+	pub fn error_count(&self) -> usize {
+		self.diagnostics.iter().filter(|d| d.severity == Severity::Error).count()
+	}
+
+	pub fn write_report(&self, out: &mut impl fmt::Write) -> fmt::Result {
+		// Problems with instruments or note IDs are shown in the summary below.
+		for diagnostic in self.diagnostics.iter().filter(|d| d.location.is_some()) {
+			if diagnostic.severity == Severity::Warning {
+				write!(out, "Warning: ")?;
+			}
+			writeln!(out, "{}", diagnostic)?;
+		}
+		if self.note_id_count > 512 {
+			writeln!(out, "More than 512 different note IDs!")?;
+		}
+		writeln!(out, "Inst V Name                   Length Repeat Idx Count  Low High 9xx IDs Error?")?;
+		for inst in &self.instruments {
+			match &inst.usage {
+				None => writeln!(out, "{:02}     {:<22}", inst.number, inst.name)?,
+				Some(usage) => {
+					let version = match (usage.is_cinter, inst.version) {
+						(true, Some(v)) => v.number().to_string(),
+						_ => " ".to_string(),
+					};
+					let replen = if usage.replen == 0 { String::new() } else { (usage.replen * 2).to_string() };
+					writeln!(out, "{:02} {} {:1} {:<22} {:6} {:>6}  {:2} {:5}  {:>3}  {:>3} {:3} {:3} {}",
+						inst.number, if usage.is_cinter { 'C' } else { 'R' }, version, inst.name,
+						usage.length * 2, replen, usage.index, usage.count,
+						notename(Some(usage.min_note)), notename(Some(usage.max_note)),
+						usage.offsets - 1, usage.note_ids, usage.message)?;
+				}
+			}
+		}
+		writeln!(out, "Uncompressed music data size: {:7} bytes", self.song_data.len())?;
+		if self.raw_inst_size > 0 {
+			writeln!(out, "Total raw instrument size:    {:7} bytes", self.raw_inst_size * 2)?;
+		}
+		writeln!(out, "Total instrument memory:      {:7} bytes", self.total_inst_size * 2)?;
+		writeln!(out, "Approx. precalc time on 68000:{:7} seconds", (self.total_inst_time + 0.5) as i64)?;
+		let time = |vblanks: usize| ((vblanks + 25) / 3000, (vblanks + 25) % 3000 / 50);
+		let (m, s) = time(self.music_length);
+		writeln!(out, "Music duration:               {:7} vblanks ({}:{:02})", self.music_length, m, s)?;
+		let (m, s) = time(self.restart);
+		writeln!(out, "Restart position:             {:7} vblanks ({}:{:02})", self.restart, m, s)?;
+		if let Some(deviation) = self.timing_deviation {
+			writeln!(out, "Max. timing deviation:        {:7.1} ms", deviation)?;
+		}
+		writeln!(out, "Number of different note IDs:   {:5}", self.note_id_count)?;
+		writeln!(out, "Number of different data words: {:5}", self.data_word_count)?;
+		if self.trim_size > 0 {
+			if self.trimmed {
+				writeln!(out, "Trimming saved {} bytes and {:.1} seconds of precalc.", self.trim_size * 2, self.trim_time)?;
+			} else {
+				for inst in &self.instruments {
+					if let Some(usage) = inst.usage.as_ref().filter(|usage| usage.played_length < usage.length) {
+						writeln!(out, "Instrument {:02} is played up to {} of {} bytes.", inst.number, usage.played_length * 2, usage.length * 2)?;
+					}
+				}
+				writeln!(out, "Trimming would save {} bytes and {:.1} seconds of precalc.", self.trim_size * 2, self.trim_time)?;
+			}
+		}
+		match self.error_count() {
+			0 => writeln!(out, "No errors."),
+			n => writeln!(out, "{} error{}.", n, if n > 1 { "s" } else { "" }),
+		}
+	}
+}
+
+#[derive(Default)]
+struct ErrorLog {
+	diagnostics: Vec<Diagnostic>,
+	reported: HashSet<(String, usize, usize, usize)>,
+}
+
+impl ErrorLog {
+	/// Report each error once per pattern, even if the pattern is played several times.
+	fn error(&mut self, code: DiagnosticCode, message: impl Into<String>, location: Location) {
+		self.report(Severity::Error, code, message.into(), location);
+	}
+
+	fn warning(&mut self, code: DiagnosticCode, message: impl Into<String>, location: Location) {
+		self.report(Severity::Warning, code, message.into(), location);
+	}
+
+	fn report(&mut self, severity: Severity, code: DiagnosticCode, message: String, location: Location) {
+		if self.reported.insert((message.clone(), location.pattern, location.track, location.row)) {
+			let mut diagnostic = Diagnostic::new(severity, code, message);
+			diagnostic.location = Some(location);
+			self.diagnostics.push(diagnostic);
+		}
+	}
+
+	fn instrument_error(&mut self, code: DiagnosticCode, message: impl Into<String>, instrument: Option<usize>) {
+		let mut diagnostic = Diagnostic::new(Severity::Error, code, message);
+		diagnostic.instrument = instrument;
+		self.diagnostics.push(diagnostic);
+	}
+}
+
+#[derive(PartialEq, Eq, Hash)]
+struct LoopState {
+	pos: usize,
+	row: usize,
+	speed: usize,
+	tempo: u8,
+	inst: [usize; 4],
+	period: [i32; 4],
+	volume: [i32; 4],
+	portamento_target: [i32; 4],
+	portamento_speed: [i32; 4],
+	offset_value: [i32; 4],
+	modulation: [Modulation; 4],
+	loop_row: [usize; 4],
+	loop_count: [usize; 4],
+}
+
+/// Position and row sequencing, following mt_music in Protracker 2.3d.
+#[derive(Default)]
+struct Flow {
+	song_pos: u8,
+	pattern_pos: usize,
+	break_pos: usize,
+	break_flag: bool,
+	jump_flag: bool,
+	delay: usize,
+	loop_row: [usize; 4],
+	loop_count: [usize; 4],
+}
+
+impl Flow {
+	fn position_jump(&mut self, position: u8) {
+		self.song_pos = position.wrapping_sub(1);
+		self.break_pos = 0;
+		self.jump_flag = true;
+	}
+
+	fn pattern_break(&mut self, row: usize) {
+		self.break_pos = row;
+		self.jump_flag = true;
+	}
+
+	fn jump_loop(&mut self, track: usize, count: u8) {
+		if count == 0 {
+			self.loop_row[track] = self.pattern_pos;
+			return;
+		}
+		if self.loop_count[track] == 0 {
+			self.loop_count[track] = count as usize;
+		} else {
+			self.loop_count[track] -= 1;
+			if self.loop_count[track] == 0 {
+				return;
+			}
+		}
+		self.break_pos = self.loop_row[track];
+		self.break_flag = true;
+	}
+
+	/// Advance after the first tick of a row, or of a repeat of a delayed row.
+	fn end_row(&mut self, song_length: u8) {
+		self.pattern_pos += 1;
+		if self.delay != 0 {
+			self.delay -= 1;
+			if self.delay != 0 {
+				self.pattern_pos -= 1;
+			}
+		}
+		if self.break_flag {
+			self.break_flag = false;
+			self.pattern_pos = self.break_pos;
+			self.break_pos = 0;
+		}
+		if self.pattern_pos >= ROW_COUNT {
+			self.next_position(song_length);
+		}
+		if self.jump_flag {
+			self.next_position(song_length);
+		}
+	}
+
+	fn next_position(&mut self, song_length: u8) {
+		self.pattern_pos = self.break_pos;
+		self.break_pos = 0;
+		self.jump_flag = false;
+		self.song_pos = self.song_pos.wrapping_add(1) & 0x7F;
+		if self.song_pos >= song_length {
+			self.song_pos = 0;
+		}
+	}
+}
+
+/// Vibrato and tremolo state of a channel.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+struct Modulation {
+	wave_control: u8,
+	vibrato_cmd: u8,
+	vibrato_pos: u8,
+	tremolo_cmd: u8,
+	tremolo_pos: u8,
+	/// Period and volume left in the hardware registers by vibrato and tremolo.
+	held_period: Option<i32>,
+	held_volume: Option<i32>,
+}
+
+impl Modulation {
+	fn note(&mut self, tr: &TrackRow) {
+		if tr.note().is_some() && tr.command != 0x3 && tr.command != 0x5 && (tr.command, tr.argument >> 4) != (0xE, 0xD) {
+			if self.wave_control & 0x04 == 0 {
+				self.vibrato_pos = 0;
+			}
+			if self.wave_control & 0x40 == 0 {
+				self.tremolo_pos = 0;
+			}
+		}
+	}
+
+	fn set_cmd(cmd: &mut u8, argument: u8) {
+		if argument & 0x0F != 0 {
+			*cmd = *cmd & 0xF0 | argument & 0x0F;
+		}
+		if argument & 0xF0 != 0 {
+			*cmd = *cmd & 0x0F | argument & 0xF0;
+		}
+	}
+
+	fn vibrato(&mut self, period: i32) -> i32 {
+		let amplitude = (wave_value(self.wave_control, self.vibrato_pos, self.vibrato_pos) * (self.vibrato_cmd & 15) as u16) >> 7;
+		let period = if (self.vibrato_pos as i8) < 0 { period - amplitude as i32 } else { period + amplitude as i32 };
+		self.vibrato_pos = self.vibrato_pos.wrapping_add(self.vibrato_cmd >> 2 & 0x3C);
+		period
+	}
+
+	fn tremolo(&mut self, volume: i32) -> i32 {
+		// The ramp waveform looks at the vibrato position, as in Protracker.
+		let amplitude = (wave_value(self.wave_control >> 4, self.tremolo_pos, self.vibrato_pos) * (self.tremolo_cmd & 15) as u16) >> 6;
+		let volume = if (self.tremolo_pos as i8) < 0 { volume - amplitude as i32 } else { volume + amplitude as i32 };
+		self.tremolo_pos = self.tremolo_pos.wrapping_add(self.tremolo_cmd >> 2 & 0x3C);
+		volume.clamp(0, 64)
+	}
+}
+
+// Negative notes index from the end of the table.
+fn table_period(note: i32) -> i32 {
+	PERIOD_TABLE[note.rem_euclid(PERIOD_TABLE.len() as i32) as usize] as i32
+}
+
+fn table_index(period: i32) -> Option<i32> {
+	PERIOD_TABLE.iter().position(|&p| p as i32 == period).map(|i| i as i32)
+}
+
+fn nearest_note(period: i32) -> i32 {
+	(0..PERIOD_TABLE.len()).min_by_key(|&i| (PERIOD_TABLE[i] as i32 - period).abs()).unwrap() as i32
+}
+
+/// First vblank of each tick at the given CIA tempos, plus the total number of vblanks,
+/// and the largest deviation in vblanks. The rounding carries the error over to the next tick.
+pub(crate) fn schedule_ticks(tempos: &[u8]) -> (Vec<usize>, f64) {
+	let mut time: f64 = 0.0;
+	let mut deviation: f64 = 0.0;
+	let mut starts = Vec::with_capacity(tempos.len() + 1);
+	for &tempo in tempos {
+		let start = time.round();
+		deviation = deviation.max((start - time).abs());
+		starts.push(start as usize);
+		time += 125.0 / tempo as f64;
+	}
+	starts.push(time.round() as usize);
+	(starts, deviation)
+}
+
+fn spread<T: Copy>(ticks: &[T], starts: &[usize]) -> Vec<T> {
+	ticks.iter().enumerate().flat_map(|(i, &value)| std::iter::repeat_n(value, starts[i + 1] - starts[i])).collect()
+}
+
+// Note of a trigger, rounding off-table periods (from retrig after a slide) up in pitch
+// to the nearest table period not above it, as for the base note of an arpeggio.
+fn trigger_note(period: i32) -> i32 {
+	table_index(period).unwrap_or_else(|| {
+		PERIOD_TABLE.iter().position(|&p| p as i32 <= period).unwrap_or(PERIOD_TABLE.len() - 1) as i32
+	})
+}
+
+pub fn convert(module: &Module) -> Conversion {
+	convert_with_options(module, &Options::default())
+}
+
+pub fn convert_with_options(module: &Module, options: &Options) -> Conversion {
+	let mut log = ErrorLog::default();
+
+	let inst_params: Vec<Option<CinterParams>> = (0 ..= INSTRUMENT_COUNT).map(|i| {
+		if i == 0 { None } else { CinterEngine::parameters_from_sample_filename(&module.instrument(i).name()).ok() }
+	}).collect();
+
+	// Parse music data
+	let mut volumedata: [Vec<i32>; 4] = Default::default();
+	let mut notedata: [Vec<usize>; 4] = Default::default();
+	let mut perioddata: [Vec<i32>; 4] = Default::default();
+	let mut offsetdata: [Vec<i32>; 4] = Default::default();
+	let mut posdata: Vec<Location> = vec![];
+	let mut row_starts: Vec<usize> = vec![];
+	let mut markers: Vec<(usize, usize, u8)> = vec![];
+	let mut tempodata: Vec<u8> = vec![];
+	let mut vblank = 0;
+
+	let mut musicspeed = 6;
+	let mut tempo = 125;
+	let mut inst = [0usize; 4];
+	let mut period = [0i32; 4];
+	let mut volume = [0i32; 4];
+	let mut portamento_target = [0i32; 4];
+	let mut portamento_speed = [0i32; 4];
+	let mut offset_value = [0i32; 4];
+	let mut start_moved = [false; 4];
+	let mut modulation = [Modulation::default(); 4];
+
+	let mut states: HashMap<LoopState, (usize, [(u8, u8); 4])> = HashMap::new();
+
+	let periodtable = |note: i32| table_period(note);
+	let last_period = PERIOD_TABLE[PERIOD_TABLE.len() - 1] as i32;
+	let first_period = PERIOD_TABLE[0] as i32;
+
+	let mut restart = 0;
+	let mut stopped = false;
+	let mut flow = Flow::default();
+	loop {
+		let (pos, r) = (flow.song_pos as usize, flow.pattern_pos);
+		let p = module.positions[pos] as usize;
+		let pattern_row = &module.patterns[p].rows[r];
+
+		// Waveform positions are kept out of the loop state, so a drifting
+		// vibrato or tremolo does not make the music endless.
+		let here = |track| Location { position: pos, pattern: p, track, row: r };
+		let mut loop_modulation = modulation;
+		let mut phases = [(0, 0); 4];
+		for ((m, tr), phase) in loop_modulation.iter_mut().zip(pattern_row).zip(&mut phases) {
+			m.note(tr);
+			*phase = (m.vibrato_pos, m.tremolo_pos);
+			m.vibrato_pos = 0;
+			m.tremolo_pos = 0;
+		}
+		let state = LoopState {
+			pos, row: r, speed: musicspeed, tempo, inst, period, volume, portamento_target, portamento_speed, offset_value,
+			modulation: loop_modulation, loop_row: flow.loop_row, loop_count: flow.loop_count,
+		};
+		if let Some(&(v, loop_phases)) = states.get(&state) {
+			for t in 0..4 {
+				if phases[t] != loop_phases[t] {
+					log.warning(DiagnosticCode::PhaseChangeOnRestart, "Vibrato or tremolo phase changes on restart", here(t));
+				}
+			}
+			restart = v;
+			break;
+		}
+		states.insert(state, (vblank, phases));
+		let row: Vec<(usize, &TrackRow, u8, u8, u8)> = pattern_row.iter().enumerate().map(|(t, tr)| {
+			let cmd = if tr.command == 0xE { 0xE0 | (tr.argument >> 4) } else { tr.command };
+			(t, tr, cmd, tr.argument >> 4, tr.argument & 0xF)
+		}).collect();
+
+		// Check for unsupported commands
+		for &(t, _, cmd, _, _) in &row {
+			if [0xE0, 0xE3, 0xE5, 0xEF].contains(&cmd) {
+				log.error(DiagnosticCode::UnsupportedCommand, format!("Unsupported command {:X}", cmd), here(t));
+			}
+		}
+
+		// Pick up speed and flow control, in channel order
+		let row_tempo = tempo;
+		let mut patterndelay = 0;
+		for &(t, tr, cmd, arg1, arg2) in &row {
+			if cmd == 0xF {
+				if tr.argument != 0 {
+					if tr.argument < 0x20 {
+						musicspeed = tr.argument as usize;
+					} else if options.cia_tempo {
+						tempo = tr.argument;
+					} else if tr.argument != 125 {
+						log.error(DiagnosticCode::TempoSet, "Tempo set", here(t));
+					}
+				} else {
+					stopped = true;
+				}
+			}
+			if cmd == 0xD {
+				let mut break_row = (arg1 * 10 + arg2) as usize;
+				if break_row > 63 {
+					log.error(DiagnosticCode::BreakOutsidePattern, "Break to position outside pattern", here(t));
+					break_row = 0;
+				}
+				flow.pattern_break(break_row);
+			}
+			if cmd == 0xB {
+				flow.position_jump(tr.argument);
+			}
+			if cmd == 0xE6 {
+				flow.jump_loop(t, arg2);
+			}
+			if cmd == 0xEE {
+				patterndelay = arg2 as usize;
+			}
+		}
+		let mut speed = musicspeed * (patterndelay + 1);
+		if stopped {
+			speed = 1;
+			restart = vblank + 1;
+		}
+
+		for &(t, tr, cmd, arg1, arg2) in &row {
+			let (arg1, arg2) = (arg1 as i32, arg2 as i32);
+			let note = tr.note();
+			let tr_inst = tr.instrument as usize;
+
+			// Vibrato and tremolo waveforms
+			let m = &mut modulation[t];
+			m.note(tr);
+			if cmd == 0xE4 {
+				m.wave_control = m.wave_control & 0xF0 | arg2 as u8;
+			}
+			if cmd == 0xE7 {
+				m.wave_control = m.wave_control & 0x0F | (arg2 as u8) << 4;
+			}
+
+			// Volume data
+			let row_start = volumedata[t].len();
+			if tr_inst != 0 {
+				volume[t] = module.instrument(tr_inst).volume as i32;
+			}
+			if cmd == 0xC {
+				// Set volume
+				volume[t] = tr.argument as i32;
+			}
+			if cmd == 0xEC && (arg2 as usize) < speed && (arg2 as usize) < musicspeed {
+				// Notecut
+				volumedata[t].extend(std::iter::repeat_n(volume[t], arg2 as usize));
+				volumedata[t].extend(std::iter::repeat_n(0, speed - arg2 as usize));
+				volume[t] = 0;
+			} else if cmd == 0x5 || cmd == 0x6 || cmd == 0xA {
+				// Volumeslide
+				let slide = if arg1 != 0 { arg1 } else { -arg2 };
+				for i in 0..speed as i32 {
+					volumedata[t].push((volume[t] + i * slide).clamp(0, 64));
+				}
+				volume[t] = *volumedata[t].last().unwrap();
+			} else if cmd == 0x7 {
+				// Tremolo
+				Modulation::set_cmd(&mut m.tremolo_cmd, tr.argument);
+				volumedata[t].push(volume[t]);
+				for _ in 1..speed {
+					volumedata[t].push(m.tremolo(volume[t]));
+				}
+			} else {
+				if cmd == 0xEA {
+					// Finevolume up
+					volume[t] = (volume[t] + arg2).min(64);
+				}
+				if cmd == 0xEB {
+					// Finevolume down
+					volume[t] = (volume[t] - arg2).max(0);
+				}
+				volumedata[t].extend(std::iter::repeat_n(volume[t], speed));
+			}
+			// Ticks before the first volume write keep the volume left by tremolo.
+			let held = if tr_inst != 0 || [0xC, 0xEA, 0xEB].contains(&cmd) {
+				0
+			} else if [0x5, 0x6, 0x7, 0xA].contains(&cmd) {
+				1
+			} else if cmd == 0xEC && (arg2 as usize) < musicspeed {
+				arg2 as usize
+			} else {
+				speed
+			}.min(speed);
+			if let Some(held_volume) = m.held_volume {
+				volumedata[t][row_start .. row_start + held].fill(held_volume);
+			}
+			if cmd == 0x7 && speed > 1 {
+				m.held_volume = volumedata[t].last().copied();
+			} else if held < speed {
+				m.held_volume = None;
+			}
+
+			// Note trigger data
+			if tr_inst != 0 {
+				if tr_inst != inst[t] && (cmd == 0x3 || cmd == 0x5) {
+					log.error(DiagnosticCode::InstrumentChangeOnToneportamento, "Instrument change on toneportamento", here(t));
+				}
+				inst[t] = tr_inst;
+			}
+			let mut notes = vec![0; speed];
+			if inst[t] == 0 {
+				if note.is_some() || (cmd == 0xE9 && arg2 != 0) {
+					log.error(DiagnosticCode::NoteWithNoInstrument, "Note with no instrument", here(t));
+				}
+			} else if cmd == 0xE9 && arg2 != 0 {
+				// Retrig note
+				for i in (0..speed).step_by(arg2 as usize) {
+					notes[i] = inst[t];
+				}
+			} else if note.is_some() && cmd == 0xED {
+				// Notedelay
+				if (arg2 as usize) < speed && (arg2 as usize) < musicspeed {
+					notes[arg2 as usize] = inst[t];
+				}
+			} else if note.is_some() && cmd != 0x3 && cmd != 0x5 {
+				notes[0] = inst[t];
+			}
+			// Protracker only resets the sample start on an instrument number, and
+			// adds the offset of a 9xx on a note twice (mt_SetRegs runs
+			// mt_CheckMoreEfx again), so later notes start further into the sample.
+			if tr_inst != 0 {
+				start_moved[t] = false;
+			}
+			if start_moved[t] && notes.iter().any(|&n| n != 0) {
+				log.warning(DiagnosticCode::OffsetCarriedOver, "Note with no instrument number after sampleoffset", here(t));
+			}
+			notedata[t].extend(notes);
+
+			// Offset data
+			let mut offsets = vec![0; speed];
+			if cmd == 0x9 {
+				if tr.argument != 0 {
+					offset_value[t] = tr.argument as i32;
+				} else if offset_value[t] == 0 {
+					log.error(DiagnosticCode::NoPreviousOffset, "No previous offset", here(t));
+				}
+				let mut offset = offset_value[t];
+				if inst[t] != 0 && note.unwrap_or(0) != 0 {
+					let inst_length = module.instrument(inst[t]).length() as i32;
+					if offset * 128 >= inst_length {
+						log.error(DiagnosticCode::OffsetBeyondEnd, "Offset beyond end of sample", here(t));
+						offset = (inst_length - 1).div_euclid(128);
+					}
+				}
+				offsets[0] = offset;
+				start_moved[t] |= offset != 0;
+			}
+			offsetdata[t].extend(offsets);
+
+			// Period data
+			let row_start = perioddata[t].len();
+			if let Some(n) = note {
+				if cmd != 0x3 && cmd != 0x5 && cmd != 0xED {
+					if cmd == 0xE1 || cmd == 0xE2 {
+						log.error(DiagnosticCode::FineslideOnNote, "Fineslide on note", here(t));
+					}
+					period[t] = periodtable(n);
+				}
+			}
+			if cmd == 0x0 && tr.argument != 0 {
+				// Arpeggio
+				if period[t] == 0 {
+					log.error(DiagnosticCode::ArpeggioWithNoBase, "Arpeggio with no base note", here(t));
+					period[t] = periodtable(0);
+				}
+				let base = PERIOD_TABLE.iter().position(|&tp| tp as i32 <= period[t]).unwrap() as i32;
+				if periodtable(base) != period[t] {
+					log.error(DiagnosticCode::ArpeggioInvalidBase, "Arpeggio with invalid base pitch (after slide)", here(t));
+				}
+				let mut arpnotes = [base, base + arg1, base + arg2];
+				for arpnote in &mut arpnotes[1..] {
+					if *arpnote >= PERIOD_TABLE.len() as i32 {
+						log.error(DiagnosticCode::ArpeggioNoteTooHigh, "Arpeggio note above B-3", here(t));
+						*arpnote = PERIOD_TABLE.len() as i32 - 1;
+					}
+				}
+				for i in 0..speed {
+					perioddata[t].push(periodtable(arpnotes[(i % musicspeed) % 3]));
+				}
+			} else if cmd == 0x4 || cmd == 0x6 {
+				// Vibrato
+				if period[t] == 0 {
+					log.error(DiagnosticCode::VibratoWithNoBase, "Vibrato with no base note", here(t));
+					period[t] = periodtable(0);
+				}
+				if cmd == 0x4 {
+					Modulation::set_cmd(&mut m.vibrato_cmd, tr.argument);
+				}
+				perioddata[t].push(period[t]);
+				for _ in 1..speed {
+					perioddata[t].push(m.vibrato(period[t]));
+				}
+			} else if cmd == 0x1 || cmd == 0x2 {
+				// Portamento
+				if period[t] == 0 {
+					log.error(DiagnosticCode::PortamentoWithNoSource, "Portamento with no source", here(t));
+					period[t] = periodtable(0);
+				}
+				let slide = if cmd == 0x1 { -(tr.argument as i32) } else { tr.argument as i32 };
+				for i in 0..speed as i32 {
+					perioddata[t].push((period[t] + i * slide).clamp(last_period, first_period));
+				}
+				period[t] = *perioddata[t].last().unwrap();
+			} else if cmd == 0x3 || cmd == 0x5 {
+				// Toneportamento
+				if let Some(n) = note {
+					portamento_target[t] = periodtable(n);
+				}
+				if cmd == 0x3 && tr.argument != 0 {
+					portamento_speed[t] = tr.argument as i32;
+				}
+				if period[t] == 0 {
+					log.error(DiagnosticCode::ToneportamentoWithNoSource, "Toneportamento with no source", here(t));
+					period[t] = periodtable(0);
+				}
+				if portamento_target[t] == 0 {
+					log.error(DiagnosticCode::ToneportamentoWithNoTarget, "Toneportamento with no target", here(t));
+					portamento_target[t] = period[t];
+				}
+				if portamento_speed[t] == 0 {
+					log.error(DiagnosticCode::ToneportamentoWithNoSpeed, "Toneportamento with no speed", here(t));
+				}
+				perioddata[t].push(period[t]);
+				for _ in 0..speed - 1 {
+					if portamento_target[t] > period[t] {
+						period[t] = (period[t] + portamento_speed[t]).min(portamento_target[t]);
+					} else {
+						period[t] = (period[t] - portamento_speed[t]).max(portamento_target[t]);
+					}
+					perioddata[t].push(period[t]);
+				}
+			} else if let (Some(n), 0xED) = (note, cmd) {
+				// Notedelay
+				if (arg2 as usize) < speed && (arg2 as usize) < musicspeed {
+					perioddata[t].extend(std::iter::repeat_n(period[t], arg2 as usize));
+					perioddata[t].extend(std::iter::repeat_n(periodtable(n), speed - arg2 as usize));
+				} else {
+					perioddata[t].extend(std::iter::repeat_n(period[t], speed));
+				}
+				period[t] = periodtable(n);
+			} else {
+				if cmd == 0xE1 && note.is_none() {
+					// Fineslide up
+					period[t] = (period[t] - arg2).max(last_period);
+				}
+				if cmd == 0xE2 && note.is_none() {
+					// Fineslide down
+					period[t] = (period[t] + arg2).min(first_period);
+				}
+				perioddata[t].extend(std::iter::repeat_n(period[t], speed));
+			}
+			// Ticks before the first period write keep the period left by vibrato.
+			let held = if note.is_some() {
+				if cmd == 0xED && (arg2 as usize) < musicspeed { arg2 as usize } else if cmd == 0xED { speed } else { 0 }
+			} else if [0x9, 0xB, 0xC, 0xD, 0xF].contains(&cmd) {
+				1
+			} else if cmd & 0xF0 == 0xE0 && cmd != 0xE1 && cmd != 0xE2 && (cmd != 0xE9 || arg2 == 0) {
+				speed
+			} else {
+				0
+			}.min(speed);
+			if let Some(held_period) = m.held_period {
+				perioddata[t][row_start .. row_start + held].fill(held_period);
+			}
+			if (cmd == 0x4 || cmd == 0x6) && speed > 1 {
+				m.held_period = perioddata[t].last().copied();
+			} else if held < speed {
+				m.held_period = None;
+			}
+		}
+
+		// Advance
+		row_starts.push(vblank);
+		for &(t, tr, cmd, _, _) in &row {
+			if cmd == 0x8 {
+				markers.push((vblank, t, tr.argument));
+			}
+		}
+		posdata.extend(std::iter::repeat_n(here(0), speed));
+		// A new tempo takes effect when the CIA timer next reloads, after the first tick.
+		tempodata.push(row_tempo);
+		tempodata.extend(std::iter::repeat_n(tempo, speed - 1));
+		vblank += speed;
+		if stopped {
+			break;
+		}
+
+		// Pattern loops are repeated on every repeat of a delayed row.
+		flow.delay = patterndelay + 1;
+		flow.end_row(module.song_length);
+		for _ in 0..patterndelay {
+			for &(t, _, cmd, _, arg2) in &row {
+				if cmd == 0xE6 {
+					flow.jump_loop(t, arg2);
+				}
+			}
+			flow.end_row(module.song_length);
+		}
+	}
+
+	// Spread the ticks over the vblanks
+	let mut timing_deviation = None;
+	if tempodata.iter().any(|&t| t != 125) {
+		let (starts, deviation) = schedule_ticks(&tempodata);
+		for track in 0..4 {
+			// Triggers in ticks without a vblank of their own are played in the next vblank.
+			let mut pending = None;
+			let mut sources = vec![];
+			for tick in 0..vblank {
+				if notedata[track][tick] != 0 {
+					pending = Some(tick);
+				}
+				for i in starts[tick]..starts[tick + 1] {
+					let trigger = if i == starts[tick] { pending.take() } else { None };
+					sources.push((trigger.unwrap_or(tick), trigger.is_some()));
+				}
+			}
+			notedata[track] = sources.iter().map(|&(tick, trigger)| if trigger { notedata[track][tick] } else { 0 }).collect();
+			offsetdata[track] = sources.iter().map(|&(tick, trigger)| if trigger { offsetdata[track][tick] } else { 0 }).collect();
+			volumedata[track] = sources.iter().map(|&(tick, _)| volumedata[track][tick]).collect();
+			perioddata[track] = sources.iter().map(|&(tick, _)| perioddata[track][tick]).collect();
+		}
+		posdata = spread(&posdata, &starts);
+		row_starts.iter_mut().for_each(|tick| *tick = starts[*tick]);
+		markers.iter_mut().for_each(|(tick, _, _)| *tick = starts[*tick]);
+		restart = starts[restart];
+		vblank = starts[vblank];
+		timing_deviation = Some(deviation * 1000.0 / VBLANK_RATE as f64);
+	}
+
+	// Find note ranges and count notes per instrument
+	let mut minmax_note: BTreeMap<(usize, i32), (i32, i32)> = BTreeMap::new();
+	let mut inst_counts = [0usize; 32];
+	for track in 0..4 {
+		for i in 0..vblank {
+			let (inst, per, offset) = (notedata[track][i], perioddata[track][i], offsetdata[track][i]);
+			if inst != 0 {
+				inst_counts[inst] += 1;
+				if table_index(per).is_none() {
+					log.error(DiagnosticCode::NonNotePeriod, format!("Note triggered at non-note period {}", per), Location { track, ..posdata[i] });
+				}
+				let note = trigger_note(per);
+				let entry = minmax_note.entry((inst, offset)).or_insert((note, note));
+				entry.0 = entry.0.min(note);
+				entry.1 = entry.1.max(note);
+			}
+		}
+	}
+
+	// List of used instruments
+	let mut inst_list: Vec<usize> = (0..32).filter(|&i| inst_counts[i] != 0).collect();
+	let sort_key = |i: usize| if inst_params[i].is_none() { 99999 - i } else { inst_counts[i] };
+	inst_list.sort_by_key(|&i| std::cmp::Reverse(sort_key(i)));
+
+	// Build note ID mapping table
+	let mut note_id = 0usize;
+	let mut note_ids = HashMap::new();
+	let mut note_range_list = vec![];
+	let mut note_id_start = vec![0];
+	for &inst in &inst_list {
+		minmax_note.entry((inst, 0)).or_insert((0, 0));
+		for (&(_, offset), &(note_min, note_max)) in minmax_note.range((inst, i32::MIN) ..= (inst, i32::MAX)) {
+			note_range_list.push((note_min, note_max, offset));
+			for n in note_min ..= note_max {
+				note_ids.insert((inst, offset, n), note_id);
+				note_id += 1;
+			}
+		}
+		note_id_start.push(note_id);
+	}
+
+	if note_id > 512 {
+		log.instrument_error(DiagnosticCode::TooManyNoteIds, "More than 512 different note IDs", None);
+	}
+
+	// Export notes
+	let mut dataset = HashSet::new();
+	let mut track_data: [Vec<u16>; 4] = Default::default();
+	for track in 0..4 {
+		let mut initial = true;
+		let mut pvol = 0;
+		let mut pper = 0;
+		let mut pdper = 0;
+		for i in 0..vblank {
+			let location = Location { track, ..posdata[i] };
+			let (mut vol, mut per, inst, offset) = (volumedata[track][i], perioddata[track][i], notedata[track][i], offsetdata[track][i]);
+			if vol == 64 {
+				vol = 63;
+			}
+			let data;
+			if inst != 0 {
+				let id = note_ids[&(inst, offset, trigger_note(per))];
+				data = 0x8000 | ((id as i32) << NOTE_SHIFT) | (vol << VOLUME_SHIFT);
+				initial = false;
+				pdper = 0;
+			} else if initial {
+				data = 0;
+			} else {
+				let dper = (per - pper) & 511;
+				let dvol = (vol - pvol) & 63;
+				match table_index(per) {
+					Some(note) if per != pper && dper != pdper => {
+						data = ((NOTE_ABS_MASK | note) << NOTE_SHIFT) | (dvol << VOLUME_SHIFT);
+						pdper = 0;
+					},
+					_ => {
+						let in_range = (-256..=255).contains(&(per - pper));
+						if in_range && ((dper >> 7) ^ (dper >> 6)) & 1 == 0 {
+							data = (dper << NOTE_SHIFT) | (dvol << VOLUME_SHIFT);
+							pdper = dper;
+						} else {
+							if !in_range {
+								log.error(DiagnosticCode::SlideOutOfRange, format!("Slide value out of range (from {} to {})", pper, per), location);
+							} else {
+								log.error(DiagnosticCode::UnsupportedSlide, "Unsupported slide value", location);
+							}
+							// Fall back to the nearest note
+							let note = nearest_note(per);
+							per = PERIOD_TABLE[note as usize] as i32;
+							data = ((NOTE_ABS_MASK | note) << NOTE_SHIFT) | (dvol << VOLUME_SHIFT);
+							pdper = 0;
+						}
+					},
+				}
+			}
+			track_data[track].push(data as u16);
+			dataset.insert(data as u16);
+			pvol = vol;
+			pper = per;
+		}
+
+		if stopped {
+			track_data[track].push(0);
+		}
+	}
+
+	while restart > 0 && (0..4).all(|t| track_data[t][restart - 1] == *track_data[t].last().unwrap()) {
+		for data in &mut track_data {
+			data.pop();
+		}
+		restart -= 1;
+	}
+
+	let music_length = track_data[0].len();
+
+	// Export note ranges
+	let note_ranges: Vec<NoteRange> = note_range_list.iter().map(|&(note_min, note_max, offset)| NoteRange {
+		note_min: note_min as u8,
+		count: (note_max - note_min + 1) as u8,
+		offset: (offset * 128) as u16,
+	}).collect();
+
+	// Export instrument parameters
+	let mut records = vec![InstrumentRecord { length: 0, replen: 0, params: None }; inst_list.len()];
+	let mut raw_instruments = vec![];
+	let mut raw_inst_size = 0;
+	let mut total_inst_size = 0;
+	let mut total_inst_time = 1.0;
+	let mut reports = vec![];
+	let last_nonempty_inst = (1 ..= INSTRUMENT_COUNT).filter(|&i| !module.instrument(i).name().trim().is_empty() || inst_list.contains(&i)).max().unwrap_or(0);
+	for i in 1 ..= last_nonempty_inst {
+		let inst = module.instrument(i);
+		let name = inst.name();
+		let version = (!name.is_empty()).then(|| SynthVersion::from_sample_filename(&name));
+
+		// Unused instrument?
+		let index = match inst_list.iter().position(|&li| li == i) {
+			Some(index) => index,
+			None => {
+				reports.push(InstrumentReport { number: i, name, version, usage: None });
+				continue;
+			}
+		};
+
+		// General statistics
+		let ranges: Vec<(i32, (i32, i32))> = minmax_note.iter()
+			.filter(|&(&(mi, _), _)| mi == i)
+			.map(|(&(_, offset), &range)| (offset, range))
+			.collect();
+		let min_note = ranges.iter().map(|&(_, (note_min, _))| note_min).min().unwrap();
+		let max_note = ranges.iter().map(|&(_, (_, note_max))| note_max).max().unwrap();
+		let n_note_ids = note_id_start[index + 1] - note_id_start[index];
+		let mut msg = "";
+		let mut code = DiagnosticCode::EmptyInstrument;
+
+		// Length and repeat length
+		let mut length = inst.length();
+		if length < 1 {
+			msg = "Empty!";
+			length = 1;
+		}
+		let replen;
+		let (repoffset, inst_replen) = (inst.repeat_offset as usize, inst.repeat_length as usize);
+		if repoffset == 0 && inst_replen <= 1 {
+			// CinterConvert.py tries to trim trailing silence here, but compares bytes
+			// to a str, so the trim never happens. Keep the length for identical output.
+			replen = 0;
+		} else {
+			replen = inst_replen;
+			if repoffset + inst_replen != inst.length() {
+				msg = "Repeat is not at end!";
+				code = DiagnosticCode::RepeatNotAtEnd;
+			}
+		}
+		total_inst_size += length;
+
+		let p = inst_params[i];
+		if let Some(p) = p {
+			// Parameters on word form for synth code
+			let words = WordParameters::from_params(SynthVersion::from_sample_filename(&name), &p);
+			records[index] = InstrumentRecord { length: length as u16, replen: replen as u16, params: Some(words) };
+			let mut sample_time = 42 + 2 * (p.mdist as i32 + p.bdist as i32 + p.fdist as i32) + 3 * p.vpower as i32;
+			sample_time += [words.mpitchdecay, words.bpitchdecay, words.moddecay].iter().filter(|&&d| d != 0).count() as i32 * 7;
+			total_inst_time += sample_time as f64 * length as f64 * 0.0000075;
+		} else {
+			records[index] = InstrumentRecord { length: length as u16, replen: replen as u16, params: None };
+			raw_instruments.push((i, length));
+			raw_inst_size += length;
+		}
+
+		if !msg.is_empty() {
+			log.instrument_error(code, msg, Some(i));
+		}
+
+		reports.push(InstrumentReport {
+			number: i,
+			name,
+			version,
+			usage: Some(InstrumentUsage {
+				is_cinter: p.is_some(),
+				length,
+				replen,
+				index,
+				count: inst_counts[i],
+				min_note,
+				max_note,
+				offsets: ranges.len(),
+				note_ids: n_note_ids,
+				played_length: length,
+				message: msg.to_string(),
+			}),
+		});
+	}
+
+	let mut song = SongData { instruments: records.clone(), note_ranges, restart, tracks: track_data };
+
+	// Trim instruments to their played lengths. An instrument played into
+	// its repeat must keep its full length, or the repeat would move.
+	let played = played_lengths(&song)
+		.unwrap_or_else(|_| records.iter().map(|record| record.length as usize).collect());
+	let mut trim_size = 0;
+	let mut trim_time = 0.0;
+	let mut trimmed_records = records.clone();
+	for (record, &played) in trimmed_records.iter_mut().zip(&played) {
+		let length = record.length as usize;
+		if played.max(1) < length && played <= length - record.replen as usize {
+			let original = *record;
+			record.length = played.max(1) as u16;
+			record.replen = 0;
+			trim_size += length - record.length as usize;
+			let saved = instrument_cost(Cpu::M68000, &original).cycles - instrument_cost(Cpu::M68000, record).cycles;
+			trim_time += saved as f64 / Cpu::M68000.clock();
+		}
+	}
+	for usage in reports.iter_mut().filter_map(|report| report.usage.as_mut()) {
+		let record = &trimmed_records[usage.index];
+		usage.played_length = record.length as usize;
+		if options.trim {
+			usage.length = record.length as usize;
+			usage.replen = record.replen as usize;
+		}
+	}
+	if options.trim {
+		song.instruments = trimmed_records;
+		total_inst_size -= trim_size;
+		total_inst_time -= trim_time;
+		raw_inst_size = song.instruments[..raw_instruments.len()].iter().map(|record| record.length as usize).sum();
+		for (i, length) in &mut raw_instruments {
+			*length = song.instruments[inst_list.iter().position(|li| li == i).unwrap()].length as usize;
+		}
+	}
+
+	let song_data = song.write().unwrap_or_else(|e| {
+		log.instrument_error(DiagnosticCode::UnplayableSongData, format!("Song data cannot be played: {}", e), None);
+		vec![]
+	});
+
+	let mut raw_data = vec![];
+	for &(i, length) in &raw_instruments {
+		let samples = &module.instrument(i).samples;
+		raw_data.extend(samples[.. (length * 2).min(samples.len())].iter().map(|&s| s as u8));
+	}
+
+	Conversion {
+		song_data,
+		raw_data,
+
+		diagnostics: log.diagnostics,
+		instruments: reports,
+
+		raw_inst_size,
+		total_inst_size,
+		total_inst_time,
+		music_length,
+		restart,
+		note_id_count: note_id,
+		timing_deviation,
+		data_word_count: dataset.len(),
+		rows: row_starts.iter().filter(|&&v| v < music_length).map(|&v| (v, posdata[v])).collect(),
+		markers: markers.into_iter().filter(|&(v, _, _)| v < music_length).collect(),
+		channels: [0, 1, 2, 3],
+		trim_size,
+		trim_time,
+		trimmed: options.trim,
+	}
+}
@@ -0,0 +1,216 @@
+use std::iter::Sum;
+use std::ops::Add;
+
+use crate::song::InstrumentRecord;
+
+// Fields of the Cinter working memory, as laid out in Cinter4.S
+const SAMPLE_STATE: usize = 3 * 4; // c_SampleState
+const PERIOD_TABLE: usize = (36 + 1) * 2; // c_PeriodTable
+const TRACK_SIZE: usize = 2; // c_TrackSize
+const POINTERS: usize = 4 * 4; // c_InstPointer, c_MusicPointer, c_MusicEnd, c_MusicLoop
+const MUSIC_STATE: usize = 4 * 3 * 4; // c_MusicState
+const DMA_STATE: usize = 2 + 2; // c_dma and c_waitline, only without CINTER_MANUAL_DMA
+const INSTRUMENTS: usize = 32 * 2 * 4; // c_Instruments
+const SINE_TABLE: usize = 16384 * 2; // c_Sinus, CINTER_DEGREES words
+
+/// Size of the Cinter working memory (c_SIZE in Cinter4.S).
+pub const WORK_MEMORY: usize = work_memory(false);
+
+/// Size of the Cinter working memory, which is smaller when the player is
+/// assembled with CINTER_MANUAL_DMA set.
+pub const fn work_memory(manual_dma: bool) -> usize {
+	let dma_state = if manual_dma { 0 } else { DMA_STATE };
+	SAMPLE_STATE + PERIOD_TABLE + TRACK_SIZE + POINTERS + MUSIC_STATE + dma_state + INSTRUMENTS + SINE_TABLE
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Cpu {
+	/// Amiga 500 CPU.
+	M68000,
+	/// Amiga 1200 CPU, running from chip memory.
+	M68020,
+}
+
+impl Cpu {
+	pub const ALL: [Cpu; 2] = [Cpu::M68000, Cpu::M68020];
+
+	pub fn name(self) -> &'static str {
+		match self {
+			Cpu::M68000 => "68000 @ 7MHz",
+			Cpu::M68020 => "68020 @ 14MHz",
+		}
+	}
+
+	/// PAL clock frequency in Hz.
+	pub fn clock(self) -> f64 {
+		match self {
+			Cpu::M68000 => 7093790.0,
+			Cpu::M68020 => 14187580.0,
+		}
+	}
+
+	fn timing(self) -> &'static Timing {
+		match self {
+			Cpu::M68000 => &TIMING_68000,
+			Cpu::M68020 => &TIMING_68020,
+		}
+	}
+}
+
+// Cycle counts for the parts of CinterMakeSinus and CinterMakeInstruments.
+// The 68000 counts are summed from the instruction timings. The 68020 counts
+// are approximate cache case timings, where multiplications take constant time.
+struct Timing {
+	// One iteration of the sine table loop, except multiplications
+	sine: u32,
+	// Setup and end of the instrument loop
+	instrument: u32,
+	// One pass of the sample loop with zero distortions and decays, except multiplications
+	sample: u32,
+	// LONGMUL, except its two mulu.w
+	longmul: u32,
+	// mulu.w, plus cycles per set bit in the source
+	mulu: u32,
+	mulu_bit: u32,
+	// muls.w 0(a1),d0, plus cycles per bit transition in the source
+	muls: u32,
+	muls_transition: u32,
+	// Extra iteration of a distortion loop
+	dist: u32,
+	// Extra iteration of the vpower loop, except the muls.w
+	vpower: u32,
+	// Non-zero decay, except the LONGMUL, and the extra add.l for a growing one
+	decay: u32,
+	decay_growth: u32,
+}
+
+const TIMING_68000: Timing = Timing {
+	sine: 176,
+	instrument: 190,
+	// .sampleloop with one pass of each distortion loop and all decays zero:
+	// parameters: move.l a2,a3 4, move.w (a3)+,d4 8 = 12
+	// modulation wave: move.l a6,a1 4, move.w d6,d2 4, move.l (a1)+,d0 12, lsr.l #2 12,
+	//   lsr.w #2 10, add.w 4, move.w (a0,d0.w) 14, sub.w # 8, bcc.b 8, lsl.w #4 14 = 90
+	// modulation strength: move.w 4, add.w # 8, move.l (a1) 12, lsr.l #3 14,
+	//   move.l (a1)+ 12, lsr.l #2 12, sub.l 8 = 70
+	// base wave: move.w 4, move.l (a1)+ 12, lsr.l #2 12, sub.l 8,
+	//   lsr.w #2 10, add.w 4, move.w (a0,d0.w) 14, sub.w # 8, bcc.b 8, lsl.w #4 14 = 94
+	// amplitude: move.w (a1)+ 8, add.l 8, swap 4, sub.w # 8, bcc.b 8, lsl.w #4 14 = 50
+	// final distortion: bra.b 10, sub.w # 8, bcc.b 8 = 26
+	// write sample: add.w 4, bvc.b 10, asr.w #8 22, move.b d0,(a4)+ 8 = 44
+	// attack-decay: move.w (a3)+ 8, sub.w d1,(a1) 12, bvc.b 10, bpl.b 10 = 40
+	// decays: move.l a6,a1 4, 3 * (move.l (a1) 12, move.w (a3)+ 8, beq.b 10, move.l d0,(a1)+ 12) = 130
+	// loop: addq.l 8, cmp.l 6, blt.w 10 = 24
+	sample: 580,
+	longmul: 24,
+	mulu: 38,
+	mulu_bit: 2,
+	muls: 46,
+	muls_transition: 2,
+	dist: 46,
+	vpower: 30,
+	decay: 12,
+	decay_growth: 12,
+};
+
+const TIMING_68020: Timing = Timing {
+	sine: 72,
+	instrument: 80,
+	sample: 276,
+	longmul: 14,
+	mulu: 27,
+	mulu_bit: 0,
+	muls: 32,
+	muls_transition: 0,
+	dist: 25,
+	vpower: 16,
+	decay: 6,
+	decay_growth: 4,
+};
+
+// Average source bits for data-dependent multiplications
+const AVERAGE_WAVE_BITS: u64 = 8;
+const AVERAGE_AMPLITUDE_TRANSITIONS: u64 = 8;
+
+/// Estimated precalculation cycles and instrument memory.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Cost {
+	pub cycles: u64,
+	/// Bytes of instrument space.
+	pub memory: usize,
+}
+
+impl Cost {
+	pub fn seconds(&self, cpu: Cpu) -> f64 {
+		self.cycles as f64 / cpu.clock()
+	}
+}
+
+impl Add for Cost {
+	type Output = Cost;
+
+	fn add(self, other: Cost) -> Cost {
+		Cost { cycles: self.cycles + other.cycles, memory: self.memory + other.memory }
+	}
+}
+
+impl Sum for Cost {
+	fn sum<I: Iterator<Item = Cost>>(iter: I) -> Cost {
+		iter.fold(Cost::default(), Add::add)
+	}
+}
+
+// Total number of set bits in the low words of 0 .. n.
+fn index_bits(n: u64) -> u64 {
+	(0..16).map(|b| {
+		let period = 2 << b;
+		(n / period) * (period / 2) + (n % period).saturating_sub(period / 2)
+	}).sum()
+}
+
+/// Cost of generating an instrument. Raw instruments only take memory.
+pub fn instrument_cost(cpu: Cpu, record: &InstrumentRecord) -> Cost {
+	let memory = record.length as usize * 2;
+	let p = match &record.params {
+		Some(p) => p,
+		None => return Cost { cycles: 0, memory },
+	};
+	let t = cpu.timing();
+	let mulu = |bits: u64| (t.mulu as u64 + t.mulu_bit as u64 * bits) * 2;
+
+	// The first two samples are cleared, the rest are computed.
+	let samples = (memory as u64).saturating_sub(2);
+	let nibble = |shift: u16| (p.dist >> shift & 15) as u64;
+	let mut per_sample = t.sample as u64 + t.longmul as u64 * 3 + mulu(0) * 3;
+	per_sample += t.mulu_bit as u64 * 2 * AVERAGE_WAVE_BITS;
+	per_sample += t.muls as u64 + t.muls_transition as u64 * AVERAGE_AMPLITUDE_TRANSITIONS;
+	per_sample += (nibble(12) + nibble(8) + nibble(0)) * t.dist as u64;
+	per_sample += nibble(4) * (t.vpower as u64 + t.muls as u64 + t.muls_transition as u64 * AVERAGE_AMPLITUDE_TRANSITIONS);
+	for decay in [p.mpitchdecay, p.moddecay, p.bpitchdecay] {
+		if decay != 0 {
+			per_sample += t.decay as u64 + t.longmul as u64 + mulu(decay.count_ones() as u64);
+			if decay & 0x8000 == 0 {
+				per_sample += t.decay_growth as u64;
+			}
+		}
+	}
+	// The two wave multiplications use the sample index
+	let index_cycles = t.mulu_bit as u64 * 4 * index_bits(samples);
+
+	Cost {
+		cycles: t.instrument as u64 + per_sample * samples + index_cycles,
+		memory,
+	}
+}
+
+/// Cost of CinterInit for an instrument list, including the sine table.
+pub fn init_cost(cpu: Cpu, instruments: &[InstrumentRecord]) -> Cost {
+	let t = cpu.timing();
+	let mut sine = 0;
+	for i in 1..4096u32 {
+		let square = (i * i) >> 8;
+		let bits = (i.count_ones() * 2 + square.count_ones() * 2) as u64;
+		sine += t.sine as u64 + t.mulu as u64 * 4 + t.mulu_bit as u64 * bits;
+	}
+	Cost { cycles: sine, memory: 0 } + instruments.iter().map(|inst| instrument_cost(cpu, inst)).sum()
+}
@@ -4,10 +4,63 @@ use std::sync::Arc;
 
 pub const PARAMETER_COUNT: usize = 12;
 
+/// Synth parameters as the steps written in sample names:
+/// 0-100 for the first eight and 0-10 for the last four.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CinterParams {
+	pub attack: u8,
+	pub decay: u8,
+	pub mpitch: u8,
+	pub mpitchdecay: u8,
+	pub bpitch: u8,
+	pub bpitchdecay: u8,
+	pub mod_: u8,
+	pub moddecay: u8,
+	pub mdist: u8,
+	pub bdist: u8,
+	pub vpower: u8,
+	pub fdist: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EngineMode {
+	/// Floating-point sine table. Close to, but not exactly, what the player computes.
+	Float,
+	/// Integer arithmetic reproducing CinterMakeInstruments in Cinter4.S bit for bit.
+	Exact,
+}
+
+/// Synth generation an instrument name belongs to. The generations share the
+/// sample generator but map pitch and decay parameters differently.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SynthVersion {
+	/// Names starting with a letter, which is free to choose and kept when saving.
+	Cinter3 { prefix: char },
+	/// Names starting with a digit (the number of chord tones).
+	Cinter4,
+}
+
 pub struct CinterEngine {
+	mode: EngineMode,
 	sine_table: Vec<i16>,
 }
 
+/// Instrument parameters on the word form read by CinterMakeInstruments in Cinter4.S.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WordParameters {
+	pub mpitch: u16,
+	pub mod_: u16,
+	pub bpitch: u16,
+	/// Negated attack step.
+	pub attack: u16,
+	/// Four nibbles: mdist, bdist, vpower, fdist.
+	pub dist: u16,
+	pub decay: u16,
+	pub mpitchdecay: u16,
+	pub moddecay: u16,
+	pub bpitchdecay: u16,
+}
+
 #[derive(Clone)]
 pub struct CinterInstrument {
 	layers: Vec<CinterInstrumentLayer>,
@@ -40,15 +93,182 @@ struct CinterInstrumentLayer {
 	amp_delta: i32,
 }
 
+impl Default for CinterParams {
+	fn default() -> Self {
+		CinterParams::from_steps([5, 40, 53, 50, 65, 50, 20, 40, 0, 0, 1, 2])
+	}
+}
+
+impl CinterParams {
+	/// Steps in parameter order, clamped to the range of each parameter.
+	pub fn from_steps(steps: [u8; PARAMETER_COUNT]) -> Self {
+		let mut params = CinterParams::from_fields(steps);
+		for (index, field) in params.fields_mut().into_iter().enumerate() {
+			*field = (*field).min(Self::max_step(index));
+		}
+		params
+	}
+
+	pub fn steps(&self) -> [u8; PARAMETER_COUNT] {
+		[
+			self.attack, self.decay, self.mpitch, self.mpitchdecay, self.bpitch, self.bpitchdecay,
+			self.mod_, self.moddecay, self.mdist, self.bdist, self.vpower, self.fdist,
+		]
+	}
+
+	pub fn max_step(index: usize) -> u8 {
+		if index < 8 { 100 } else { 10 }
+	}
+
+	pub fn step(&self, index: usize) -> u8 {
+		self.steps()[index]
+	}
+
+	pub fn set_step(&mut self, index: usize, step: u8) {
+		*self.fields_mut()[index] = step.min(Self::max_step(index));
+	}
+
+	/// Parameter value in the 0-1 range used by the plugin and GUI.
+	pub fn normalized(&self, index: usize) -> f32 {
+		self.step(index) as f32 / Self::max_step(index) as f32
+	}
+
+	pub fn set_normalized(&mut self, index: usize, value: f32) {
+		let max = Self::max_step(index);
+		self.set_step(index, (value.clamp(0.0, 1.0) * max as f32).round() as u8);
+	}
+
+	pub fn from_normalized(values: &[f32; PARAMETER_COUNT]) -> Self {
+		let mut params = CinterParams::from_fields([0; PARAMETER_COUNT]);
+		for (index, &value) in values.iter().enumerate() {
+			params.set_normalized(index, value);
+		}
+		params
+	}
+
+	pub fn to_normalized(&self) -> [f32; PARAMETER_COUNT] {
+		let mut values = [0.0; PARAMETER_COUNT];
+		for (index, value) in values.iter_mut().enumerate() {
+			*value = self.normalized(index);
+		}
+		values
+	}
+
+	// Unclamped, as chord layers may raise the pitches above the range.
+	fn from_fields(steps: [u8; PARAMETER_COUNT]) -> Self {
+		let [attack, decay, mpitch, mpitchdecay, bpitch, bpitchdecay, mod_, moddecay, mdist, bdist, vpower, fdist] = steps;
+		CinterParams { attack, decay, mpitch, mpitchdecay, bpitch, bpitchdecay, mod_, moddecay, mdist, bdist, vpower, fdist }
+	}
+
+	fn fields_mut(&mut self) -> [&mut u8; PARAMETER_COUNT] {
+		[
+			&mut self.attack, &mut self.decay, &mut self.mpitch, &mut self.mpitchdecay, &mut self.bpitch, &mut self.bpitchdecay,
+			&mut self.mod_, &mut self.moddecay, &mut self.mdist, &mut self.bdist, &mut self.vpower, &mut self.fdist,
+		]
+	}
+}
+
+impl WordParameters {
+	/// The parameter words that the converter writes to the instrument record.
+	pub fn from_params(version: SynthVersion, params: &CinterParams) -> Self {
+		let p = params;
+		WordParameters {
+			mpitch:      version.pitch(p.mpitch) as u16,
+			mod_:        p.mod_ as u16,
+			bpitch:      version.pitch(p.bpitch) as u16,
+			attack:      (65536 - envfun(p.attack)) as u16,
+			dist:        (p.mdist as u16) << 12 | (p.bdist as u16) << 8 | (p.vpower as u16) << 4 | p.fdist as u16,
+			decay:       envfun(p.decay) as u16,
+			mpitchdecay: version.decay(p.mpitchdecay) as u16,
+			moddecay:    version.decay(p.moddecay) as u16,
+			bpitchdecay: version.decay(p.bpitchdecay) as u16,
+		}
+	}
+}
+
+impl SynthVersion {
+	pub fn from_sample_filename(name: &str) -> SynthVersion {
+		match name.chars().next() {
+			Some(c) if !c.is_ascii_digit() => SynthVersion::Cinter3 { prefix: c },
+			_ => SynthVersion::Cinter4,
+		}
+	}
+
+	/// Cinter major version, as shown in reports.
+	pub fn number(self) -> u32 {
+		match self {
+			SynthVersion::Cinter3 { .. } => 3,
+			SynthVersion::Cinter4 => 4,
+		}
+	}
+
+	pub fn pitch(self, step: u8) -> u32 {
+		match self {
+			SynthVersion::Cinter3 { .. } => pitchfun3(step),
+			SynthVersion::Cinter4 => pitchfun(step),
+		}
+	}
+
+	pub fn decay(self, step: u8) -> u32 {
+		match self {
+			SynthVersion::Cinter3 { .. } => decayfun3(step),
+			SynthVersion::Cinter4 => decayfun(step),
+		}
+	}
+
+	/// Nearest Cinter 4 equivalent of the parameters.
+	pub fn cinter4_parameters(self, params: &CinterParams) -> CinterParams {
+		fn convert3to4(step: u8, fun3: impl Fn(u8) -> u32, fun4: impl Fn(u8) -> u32) -> u8 {
+			let target = fun3(step);
+			let ((low, lowval), (high, highval)) = binary_search::binary_search((0, fun4(0)), (100, fun4(100)), |v| {
+				let val = fun4(v as u8);
+				if val < target {
+					binary_search::Direction::Low(val)
+				} else {
+					binary_search::Direction::High(val)
+				}
+			});
+			let v = if target - lowval < highval - target {
+				low
+			} else {
+				high
+			};
+			v as u8
+		}
+
+		let mut params = *params;
+		if let SynthVersion::Cinter3 { .. } = self {
+			params.mpitch = convert3to4(params.mpitch, pitchfun3, pitchfun);
+			params.mpitchdecay = convert3to4(params.mpitchdecay, decayfun3, decayfun);
+			params.bpitch = convert3to4(params.bpitch, pitchfun3, pitchfun);
+			params.bpitchdecay = convert3to4(params.bpitchdecay, decayfun3, decayfun);
+			params.moddecay = convert3to4(params.moddecay, decayfun3, decayfun);
+		}
+		params
+	}
+}
+
 impl CinterEngine {
 	pub fn new() -> Self {
+		Self::with_mode(EngineMode::Float)
+	}
+
+	pub fn with_mode(mode: EngineMode) -> Self {
 		CinterEngine {
-			sine_table: (0..16384).map(|i| {
-				((i as f32 / 16384.0 * (2.0 * PI)).sin() * 16384.0).round() as i16
-			}).collect()
+			mode,
+			sine_table: match mode {
+				EngineMode::Float => (0..16384).map(|i| {
+					((i as f32 / 16384.0 * (2.0 * PI)).sin() * 16384.0).round() as i16
+				}).collect(),
+				EngineMode::Exact => exact_sine_table(),
+			}
 		}
 	}
 
+	pub fn mode(&self) -> EngineMode {
+		self.mode
+	}
+
 	pub fn get_parameter_name(index: i32) -> String {
 		match index {
 			0 => "attack",
@@ -67,14 +287,18 @@ impl CinterEngine {
 		}.to_string()
 	}
 
-	pub fn get_parameter_text_and_label(index: i32, value: f32) -> (String, String) {
+	pub fn get_parameter_text_and_label(version: SynthVersion, index: i32, value: f32) -> (String, String) {
 		let (text, label) = match index {
 			// attack/decay envelope
-			0 | 1 => match envfun(value) {
+			0 | 1 => match envfun(p100(value) as u8) {
 				0 => (format!("infinite"), ""),
 				f => (format!("{}", 32767 / f + 1), "samples")
 			},
-			// pitch
+			// pitch, as a multiple of the base frequency in Cinter 3
+			2 | 4 if matches!(version, SynthVersion::Cinter3 { .. }) => match p100(value) {
+				0 => ("none".to_string(), ""),
+				v => (format!("{}", v * 2), "x"),
+			},
 			2 | 4 => match p100(value) {
 				0 => (format!("none"), ""),
 				v if v < 5 => (format!("{} oct", v - 5), ""),
@@ -84,7 +308,7 @@ impl CinterEngine {
 			// mod
 			6 => (format!("{}", p100(value)), ""),
 			// pitch/mod decay
-			3 | 5 | 7 => (format!("{:.5}", decayfun(value) as f32 / 65536.0), ""),
+			3 | 5 | 7 => (format!("{:.5}", version.decay(p100(value) as u8) as f32 / 65536.0), ""),
 			// dist
 			8 | 9 | 11 => (format!("{}", p10(value)), ""),
 			// vpower
@@ -94,37 +318,26 @@ impl CinterEngine {
 		(text, label.to_string())
 	}
 
-	pub fn get_parameter_resolution(index: i32) -> f32 {
-		if index < 8 { 0.01 } else { 0.1 }
-	}
-
-	pub fn sample_filename_from_parameters(params: &[f32; PARAMETER_COUNT]) -> String {
-		let mut name = "1".to_string();
-		for i in 0..PARAMETER_COUNT {
-			let repr = if i < 8 {
-				// Two digits
-				let v = p100(params[i]);
-				if v == 100 {
-					"XX".to_string()
-				} else {
-					format!("{:02}", v)
-				}
+	pub fn sample_filename_from_parameters(version: SynthVersion, params: &CinterParams) -> String {
+		let mut name = match version {
+			SynthVersion::Cinter3 { prefix } => prefix.to_string(),
+			SynthVersion::Cinter4 => "1".to_string(),
+		};
+		for (i, step) in params.steps().into_iter().enumerate() {
+			let repr = if step == CinterParams::max_step(i) {
+				"X".repeat(if i < 8 { 2 } else { 1 })
+			} else if i < 8 {
+				format!("{:02}", step)
 			} else {
-				// One digit
-				let v = p10(params[i]);
-				if v == 10 {
-					"X".to_string()
-				} else {
-					format!("{:01}", v)
-				}
+				format!("{:01}", step)
 			};
 			name += &repr;
 		}
 		name
 	}
 
-	pub fn sample_filename_from_chord_parameters(params: &[f32; PARAMETER_COUNT], chord_intervals: &[u8]) -> String {
-		let basename = Self::sample_filename_from_parameters(params);
+	pub fn sample_filename_from_chord_parameters(version: SynthVersion, params: &CinterParams, chord_intervals: &[u8]) -> String {
+		let basename = Self::sample_filename_from_parameters(version, params);
 		if !chord_intervals.is_empty() {
 			let s = |i: usize| {
 				if i < chord_intervals.len() {
@@ -150,57 +363,23 @@ impl CinterEngine {
 		basename
 	}
 
-	pub fn parameters_from_sample_filename(name: &str) -> anyhow::Result<[f32; PARAMETER_COUNT]> {
-		let legacy = !name.starts_with('1');
-		let mut params = [0f32; PARAMETER_COUNT];
-		for i in 0..PARAMETER_COUNT {
-			if i < 8 {
-				let digits: &str = name.get(i * 2 + 1 .. i * 2 + 3).ok_or_else(|| anyhow::anyhow!("Name too short"))?;
-				params[i] = if digits.eq_ignore_ascii_case("XX") {
-					100
-				} else {
-					digits.parse::<i32>()?
-				} as f32 * 0.01;
+	/// Parameters as given in the name, to be interpreted according to `SynthVersion::from_sample_filename`.
+	pub fn parameters_from_sample_filename(name: &str) -> anyhow::Result<CinterParams> {
+		let chars: Vec<char> = name.chars().collect();
+		let mut steps = [0u8; PARAMETER_COUNT];
+		for (i, step) in steps.iter_mut().enumerate() {
+			let (start, width) = if i < 8 { (i * 2 + 1, 2) } else { (i + 9, 1) };
+			let field: String = chars.get(start .. start + width).ok_or_else(|| anyhow::anyhow!("Name too short"))?.iter().collect();
+			*step = if field.eq_ignore_ascii_case(&"X".repeat(width)) {
+				CinterParams::max_step(i)
 			} else {
-				let digit = name.get(i + 9 .. i + 10).ok_or_else(|| anyhow::anyhow!("Name too short"))?;
-				params[i] = if digit.eq_ignore_ascii_case("X") {
-					10
-				} else {
-					digit.parse::<i32>()?
-				} as f32 * 0.1;
+				field.trim().parse::<u8>()?
 			};
 		}
-
-		if legacy {
-			fn convert3to4(value: f32, fun3: &mut impl FnMut(f32) -> u32,  fun4: &mut impl FnMut(f32) -> u32) -> f32 {
-				let target = fun3(value);
-				let ((low, lowval), (high, highval)) = binary_search::binary_search((0, fun4(0.0)), (100, fun4(1.0)), |v| {
-					let val = fun4(v as f32 * 0.01);
-					if val < target {
-						binary_search::Direction::Low(val)
-					} else {
-						binary_search::Direction::High(val)
-					}
-				});
-				let v = if target - lowval < highval - target {
-					low
-				} else {
-					high
-				};
-				v as f32 * 0.01
-			}
-
-			params[2] = convert3to4(params[2], &mut pitchfun3, &mut pitchfun);
-			params[3] = convert3to4(params[3], &mut decayfun3, &mut decayfun);
-			params[4] = convert3to4(params[4], &mut pitchfun3, &mut pitchfun);
-			params[5] = convert3to4(params[5], &mut decayfun3, &mut decayfun);
-			params[7] = convert3to4(params[7], &mut decayfun3, &mut decayfun);
-		}
-
-		Ok(params)
+		Ok(CinterParams::from_steps(steps))
 	}
 
-	pub fn chord_parameters_from_sample_filename(name: &str) -> anyhow::Result<([f32; PARAMETER_COUNT], Vec<u8>)> {
+	pub fn chord_parameters_from_sample_filename(name: &str) -> anyhow::Result<(CinterParams, Vec<u8>)> {
 		let chord_marker = name.get(0 .. 1).ok_or_else(|| anyhow::anyhow!("Name too short"))?;
 		match chord_marker {
 			"2" | "3" | "4" | "5" => {
@@ -236,7 +415,8 @@ impl CinterEngine {
 impl CinterInstrument {
 	pub fn new(
 		engine: Arc<CinterEngine>,
-		params: &[f32; PARAMETER_COUNT],
+		version: SynthVersion,
+		params: &CinterParams,
 		chord_intervals: &[u8],
 		length: Option<usize>,
 		repeat_start: Option<usize>,
@@ -247,19 +427,19 @@ impl CinterInstrument {
 			_ => repeat_start
 		};
 
-		let mut params = params.clone();
+		let mut params = *params;
 		if !chord_intervals.is_empty() {
-			params[3] = 0.5;
-			params[5] = 0.5;
+			params.mpitchdecay = 50;
+			params.bpitchdecay = 50;
 		}
-		let mut layers = vec![CinterInstrumentLayer::new(Arc::clone(&engine), &params)];
+		let mut layers = vec![CinterInstrumentLayer::new(Arc::clone(&engine), version, &params)];
 		for &interval in chord_intervals {
-			for pitch_index in [2, 4] {
-				if p100(params[pitch_index]) != 0 {
-					params[pitch_index] += interval as f32 * 0.01;
+			for pitch in [&mut params.mpitch, &mut params.bpitch] {
+				if *pitch != 0 {
+					*pitch += interval;
 				}
 			}
-			layers.push(CinterInstrumentLayer::new(Arc::clone(&engine), &params));
+			layers.push(CinterInstrumentLayer::new(Arc::clone(&engine), version, &params));
 		}
 
 		let mut inst = CinterInstrument {
@@ -277,6 +457,26 @@ impl CinterInstrument {
 		inst
 	}
 
+	/// Single-layer instrument from parameters on word form, as found in song data.
+	pub fn from_words(
+		engine: Arc<CinterEngine>,
+		words: &WordParameters,
+		length: usize,
+		repeat_start: Option<usize>,
+	) -> Self {
+		let mut data = Vec::with_capacity(length);
+		data.push(0);
+		data.push(0);
+		CinterInstrument {
+			layers: vec![CinterInstrumentLayer::from_words(engine, words)],
+
+			length,
+			repeat_start: repeat_start.filter(|&start| start < length),
+
+			data,
+		}
+	}
+
 	pub fn repeated_index(&self, index: usize) -> Option<usize> {
 		if index < self.length {
 			Some(index)
@@ -313,22 +513,22 @@ impl CinterInstrument {
 }
 
 impl CinterInstrumentLayer {
-	fn new(engine: Arc<CinterEngine>, params: &[f32; PARAMETER_COUNT]) -> Self {
+	fn new(engine: Arc<CinterEngine>, version: SynthVersion, params: &CinterParams) -> Self {
 		let mut layer = CinterInstrumentLayer {
 			engine,
 
-			attack:      envfun(params[0]),
-			decay:       envfun(params[1]),
-			mpitch:      pitchfun(params[2]) << 16,
-			mpitchdecay: decayfun(params[3]),
-			bpitch:      pitchfun(params[4]) << 16,
-			bpitchdecay: decayfun(params[5]),
-			mod_:        (p100(params[6]) << 16) as u32,
-			moddecay:    decayfun(params[7]),
-			mdist:       p10(params[8]),
-			bdist:       p10(params[9]),
-			vpower:      p10(params[10]),
-			fdist:       p10(params[11]),
+			attack:      envfun(params.attack),
+			decay:       envfun(params.decay),
+			mpitch:      version.pitch(params.mpitch) << 16,
+			mpitchdecay: version.decay(params.mpitchdecay),
+			bpitch:      version.pitch(params.bpitch) << 16,
+			bpitchdecay: version.decay(params.bpitchdecay),
+			mod_:        (params.mod_ as u32) << 16,
+			moddecay:    version.decay(params.moddecay),
+			mdist:       params.mdist as i32,
+			bdist:       params.bdist as i32,
+			vpower:      params.vpower as i32,
+			fdist:       params.fdist as i32,
 
 			phase:       0,
 			amp:         0,
@@ -339,7 +539,48 @@ impl CinterInstrumentLayer {
 		layer
 	}
 
+	fn from_words(engine: Arc<CinterEngine>, words: &WordParameters) -> Self {
+		// Decay words below $8000 mean factors above 1, with 0 meaning exactly 1.
+		let decay = |w: u16| -> u32 {
+			match w {
+				0 => 0x10000,
+				w if w & 0x8000 != 0 => w as u32,
+				w => w as u32 + 0x10000,
+			}
+		};
+		let attack = words.attack.wrapping_neg() as i32;
+		CinterInstrumentLayer {
+			engine,
+
+			attack,
+			decay:       words.decay as i32,
+			mpitch:      (words.mpitch as u32) << 16,
+			mpitchdecay: decay(words.mpitchdecay),
+			bpitch:      (words.bpitch as u32) << 16,
+			bpitchdecay: decay(words.bpitchdecay),
+			mod_:        (words.mod_ as u32) << 16,
+			moddecay:    decay(words.moddecay),
+			mdist:       (words.dist >> 12) as i32,
+			bdist:       (words.dist >> 8 & 15) as i32,
+			vpower:      (words.dist >> 4 & 15) as i32,
+			fdist:       (words.dist & 15) as i32,
+
+			phase:       0,
+			amp:         0,
+			amp_delta:   attack,
+		}
+	}
+
 	fn compute_sample(&mut self) -> i8 {
+		let val = match self.engine.mode {
+			EngineMode::Float => self.compute_value_float(),
+			EngineMode::Exact => self.compute_value_exact(),
+		};
+		self.advance();
+		val
+	}
+
+	fn compute_value_float(&self) -> i8 {
 		let mval = self.distort(self.sintab(mul(self.phase, self.mpitch)), self.mdist);
 		let mut val = self.distort(self.sintab(mul(self.phase, self.bpitch) + mul(mval, self.mod_)), self.bdist);
 		let mut p = self.vpower;
@@ -347,8 +588,27 @@ impl CinterInstrumentLayer {
 			val = val * self.amp / 32768;
 			p -= 1;
 		}
-		val = (self.distort(val, self.fdist) >> 7).min(127);
+		(self.distort(val, self.fdist) >> 7).min(127) as i8
+	}
 
+	// Mirrors .sampleloop in Cinter4.S, including its 16-bit index and rounding behavior.
+	fn compute_value_exact(&self) -> i8 {
+		let index = self.phase as u16 as u32;
+		let mval = self.distort(self.sintab(longmul(self.mpitch >> 2, index) as i32), self.mdist);
+		let modulation = (self.mod_ >> 3).wrapping_sub(longmul(self.mod_ >> 2, (mval + 0x8000) as u32));
+		let mut val = self.distort(self.sintab(longmul(self.bpitch >> 2, index).wrapping_sub(modulation) as i32), self.bdist) as i16;
+		for _ in 0..=self.vpower {
+			val = ((val as i32 * self.amp) >> 15) as i16;
+		}
+		let val = self.distort(val as i32, self.fdist) as i16;
+		let val = match val.checked_mul(2) {
+			Some(v) => v,
+			None => val.wrapping_mul(2).wrapping_sub(1),
+		};
+		(val >> 8) as i8
+	}
+
+	fn advance(&mut self) {
 		self.mpitch = ((self.mpitch as u64 * self.mpitchdecay as u64) >> 16) as u32;
 		self.bpitch = ((self.bpitch as u64 * self.bpitchdecay as u64) >> 16) as u32;
 		self.mod_ = ((self.mod_ as u64 * self.moddecay as u64) >> 16) as u32;
@@ -362,8 +622,6 @@ impl CinterInstrumentLayer {
 		}
 
 		self.phase += 1;
-
-		val as i8
 	}
 
 	fn sintab(&self, i: i32) -> i32 {
@@ -383,6 +641,30 @@ fn mul(v16: i32, v32: u32) -> i32 {
 	((v16 as i64 * (v32 >> 2) as i64) >> 16) as i32
 }
 
+// LONGMUL macro in Cinter4.S
+fn longmul(v32: u32, v16: u32) -> u32 {
+	((v32 as u64 * v16 as u64) >> 16) as u32
+}
+
+// Polynomial approximation from CinterMakeSinus in Cinter4.S
+fn exact_sine_table() -> Vec<i16> {
+	let mut table = vec![0i16; 16384];
+	let mut value = 0;
+	for i in 1..4096usize {
+		let square = (i * i) >> 8;
+		let mut v = (21073 - ((2373 * square) >> 16)) & 0xFFFF;
+		v = (51469 - ((v * square) >> 16)) & 0xFFFF;
+		value = ((v * i) >> 13) as i16;
+		table[i] = value;
+		table[8192 - i] = value;
+		table[8192 + i] = -value;
+		table[16384 - i] = -value;
+	}
+	table[4096] = value;
+	table[12288] = -value;
+	table
+}
+
 
 
 fn p10(value: f32) -> i32 {
@@ -393,29 +675,29 @@ fn p100(value: f32) -> i32 {
 	(value * 100.0).round() as i32
 }
 
-fn envfun(value: f32) -> i32 {
-	let v = p100(value);
+pub(crate) fn envfun(v: u8) -> i32 {
+	let v = v as i32;
 	10000 / (1 + v * v)
 }
 
-fn pitchfun(value: f32) -> u32 {
-	match p100(value) {
+pub(crate) fn pitchfun(v: u8) -> u32 {
+	match v as i32 {
 		0 => 0,
 		v if v < 5 => 8 << v,
-		v => (256.0 * ((v - 5) as f32 / 12.0).exp2()).round() as u32
+		v => (256.0 * ((v - 5) as f64 / 12.0).exp2()).round() as u32
 	}
 }
 
-fn pitchfun3(value: f32) -> u32 {
-	(p100(value) * 512) as u32
+pub(crate) fn pitchfun3(v: u8) -> u32 {
+	v as u32 * 512
 }
 
-fn decayfun(value: f32) -> u32 {
-	let v = p100(value) as f32 / 50.0 - 1.0;
+pub(crate) fn decayfun(v: u8) -> u32 {
+	let v = v as f64 / 50.0 - 1.0;
 	return ((0.0008 * v + 0.1 * v.powi(7)).exp() * 65536.0).round() as u32
 }
 
-fn decayfun3(value: f32) -> u32 {
-	let v = p100(value) as f32;
-	((-0.000002 * v * v).exp() * 65536.0).round() as u32
+pub(crate) fn decayfun3(v: u8) -> u32 {
+	let v = v as f64;
+	((-0.000002 * v * v).exp() * 65536.0).floor() as u32
 }
@@ -0,0 +1,151 @@
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::convert::Conversion;
+use crate::cost::work_memory;
+
+// Words per line of assembler and C source
+const WORDS_PER_LINE: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+	Binary,
+	/// dc.w source for vasm and Devpac.
+	Asm,
+	/// Arrays of big-endian words.
+	C,
+}
+
+impl Format {
+	/// Format by file extension: .s or .asm for assembler, .c for C and binary otherwise.
+	pub fn from_path(path: &Path) -> Format {
+		match path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase()).as_deref() {
+			Some("s" | "asm") => Format::Asm,
+			Some("c") => Format::C,
+			_ => Format::Binary,
+		}
+	}
+
+	/// Extension of the header with the sizes, written next to a source file.
+	pub fn header_extension(self) -> Option<&'static str> {
+		match self {
+			Format::Binary => None,
+			Format::Asm => Some("i"),
+			Format::C => Some("h"),
+		}
+	}
+
+	/// File contents of the data in the format, with the symbol as label or array name.
+	pub fn write(self, symbol: &str, data: &[u8]) -> Vec<u8> {
+		match self {
+			Format::Binary => data.to_vec(),
+			Format::Asm => asm_data(symbol, data).into_bytes(),
+			Format::C => c_array(symbol, data).into_bytes(),
+		}
+	}
+}
+
+/// Identifier from the name of a file, for labels and array names.
+pub fn symbol_name(path: &Path) -> String {
+	let stem = path.file_stem().map(|s| s.to_string_lossy()).unwrap_or_default();
+	let mut symbol: String = stem.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+	if !symbol.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+		symbol.insert(0, '_');
+	}
+	symbol
+}
+
+fn words(data: &[u8]) -> Vec<u16> {
+	data.chunks(2).map(|pair| u16::from_be_bytes([pair[0], pair.get(1).copied().unwrap_or(0)])).collect()
+}
+
+/// Data as dc.w lines under a label, ending with a dc.b for an odd byte.
+pub fn asm_data(label: &str, data: &[u8]) -> String {
+	let mut asm = format!("{}:\n", label);
+	for line in data.chunks(WORDS_PER_LINE * 2) {
+		let (even, odd) = line.split_at(line.len() & !1);
+		if !even.is_empty() {
+			let words: Vec<String> = words(even).iter().map(|w| format!("${:04X}", w)).collect();
+			writeln!(asm, "\tdc.w\t{}", words.join(",")).unwrap();
+		}
+		if let Some(byte) = odd.first() {
+			writeln!(asm, "\tdc.b\t${:02X}", byte).unwrap();
+		}
+	}
+	writeln!(asm, "{}_End:", label).unwrap();
+	asm
+}
+
+/// Data as an array of words, which have the byte order of the Amiga.
+/// An odd byte is padded with zero.
+pub fn c_array(name: &str, data: &[u8]) -> String {
+	let mut c = format!("const unsigned short {}[{}] = {{\n", name, data.len().div_ceil(2));
+	for line in words(data).chunks(WORDS_PER_LINE) {
+		let words: Vec<String> = line.iter().map(|w| format!("0x{:04X},", w)).collect();
+		writeln!(c, "\t{}", words.join(" ")).unwrap();
+	}
+	c += "};\n";
+	c
+}
+
+/// Bytes needed to play a converted song.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sizes {
+	pub music: usize,
+	pub raw_instruments: usize,
+	/// Instrument space passed to CinterInit in A4.
+	pub instrument_space: usize,
+	/// Cinter working memory passed in A6 (c_SIZE).
+	pub work_memory: usize,
+	/// Cinter working memory when the player is assembled with CINTER_MANUAL_DMA.
+	pub work_memory_manual_dma: usize,
+}
+
+impl Sizes {
+	pub fn new(conversion: &Conversion) -> Sizes {
+		Sizes {
+			music: conversion.song_data.len(),
+			raw_instruments: conversion.raw_data.len(),
+			instrument_space: conversion.total_inst_size * 2,
+			work_memory: work_memory(false),
+			work_memory_manual_dma: work_memory(true),
+		}
+	}
+
+	fn symbols(&self, prefix: &str) -> [(String, usize, &'static str); 5] {
+		let prefix = prefix.to_ascii_uppercase();
+		[
+			(format!("{}_MUSIC_SIZE", prefix), self.music, "Music data"),
+			(format!("{}_RAW_SIZE", prefix), self.raw_instruments, "Raw instrument data"),
+			(format!("{}_INSTRUMENT_SPACE", prefix), self.instrument_space, "Instrument space for CinterInit (A4)"),
+			(format!("{}_WORK_SIZE", prefix), self.work_memory, "Cinter working memory (A6, c_SIZE)"),
+			(format!("{}_WORK_SIZE_MANUAL_DMA", prefix), self.work_memory_manual_dma, "The same with CINTER_MANUAL_DMA"),
+		]
+	}
+
+	/// Assembler equates for the sizes in bytes.
+	pub fn asm_header(&self, prefix: &str) -> String {
+		let mut asm = String::new();
+		for (name, size, comment) in self.symbols(prefix) {
+			writeln!(asm, "{}\tequ\t{}\t; {}", name, size, comment).unwrap();
+		}
+		asm
+	}
+
+	/// C defines for the sizes in bytes, with declarations of the arrays.
+	pub fn c_header(&self, prefix: &str, arrays: &[&str]) -> String {
+		let guard = format!("{}_H", prefix.to_ascii_uppercase());
+		let mut c = format!("#ifndef {}\n#define {}\n\n", guard, guard);
+		for (name, size, comment) in self.symbols(prefix) {
+			writeln!(c, "#define {} {} /* {} */", name, size, comment).unwrap();
+		}
+		if !arrays.is_empty() {
+			c += "\n";
+			for array in arrays {
+				writeln!(c, "extern const unsigned short {}[];", array).unwrap();
+			}
+		}
+		c += "\n#endif\n";
+		c
+	}
+}
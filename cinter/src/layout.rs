@@ -0,0 +1,175 @@
+use std::fmt;
+
+use crate::convert::Conversion;
+use crate::module::CHANNEL_COUNT;
+use crate::pack::estimate_packed_size;
+use crate::song::SongData;
+
+// Channel orders keeping each track on its side of the stereo image
+const CHANNEL_ORDERS: [[usize; CHANNEL_COUNT]; 4] = [[0, 1, 2, 3], [3, 1, 2, 0], [0, 2, 1, 3], [3, 2, 1, 0]];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InstrumentOrder {
+	/// Most used first, as chosen by the converter.
+	Count,
+	/// By module instrument number.
+	Number,
+	/// First triggered first.
+	FirstUse,
+}
+
+impl InstrumentOrder {
+	pub const ALL: [InstrumentOrder; 3] = [InstrumentOrder::Count, InstrumentOrder::Number, InstrumentOrder::FirstUse];
+
+	pub fn name(self) -> &'static str {
+		match self {
+			InstrumentOrder::Count => "count",
+			InstrumentOrder::Number => "number",
+			InstrumentOrder::FirstUse => "first use",
+		}
+	}
+}
+
+/// Arrangement of the song data. Raw instruments always come first, in
+/// the order of the raw instrument data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Layout {
+	pub instrument_order: InstrumentOrder,
+	/// Module channel played by each Paula channel.
+	pub channels: [usize; CHANNEL_COUNT],
+	/// The words of all channels for each vblank in turn, rather than one
+	/// track after the other. The player cannot read this.
+	pub interleaved: bool,
+}
+
+impl Layout {
+	pub const DEFAULT: Layout = Layout { instrument_order: InstrumentOrder::Count, channels: [0, 1, 2, 3], interleaved: false };
+
+	pub fn all() -> Vec<Layout> {
+		let mut layouts = vec![];
+		for instrument_order in InstrumentOrder::ALL {
+			for channels in CHANNEL_ORDERS {
+				layouts.push(Layout { instrument_order, channels, interleaved: false });
+			}
+			layouts.push(Layout { instrument_order, interleaved: true, ..Layout::DEFAULT });
+		}
+		layouts
+	}
+
+	/// Whether the player can read the layout and play it with the same stereo image.
+	pub fn is_playable(&self) -> bool {
+		let left = |channel: usize| channel == 0 || channel == 3;
+		!self.interleaved && self.channels.iter().enumerate().all(|(paula, &channel)| left(paula) == left(channel))
+	}
+}
+
+impl fmt::Display for Layout {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let [a, b, c, d] = self.channels;
+		write!(f, "instruments by {:<9}  channels {}{}{}{}  {:<14}", self.instrument_order.name(), a, b, c, d,
+			if self.interleaved { "interleaved" } else { "track by track" })
+	}
+}
+
+// Song data in the layout, with the previous index of each instrument.
+fn arrange(conversion: &Conversion, layout: &Layout) -> anyhow::Result<(SongData, Vec<usize>)> {
+	let song = SongData::read(&conversion.song_data)?;
+	let numbers = conversion.instrument_numbers();
+	let mut first_use = vec![usize::MAX; song.instruments.len()];
+	for (vblank, frame) in song.frames(song.length())?.iter().enumerate() {
+		for trigger in frame.iter().filter_map(|channel| channel.trigger) {
+			first_use[trigger.instrument] = first_use[trigger.instrument].min(vblank);
+		}
+	}
+	let raw_count = song.instruments.iter().take_while(|inst| inst.params.is_none()).count();
+	let mut order: Vec<usize> = (0..song.instruments.len()).collect();
+	match layout.instrument_order {
+		InstrumentOrder::Count => {},
+		InstrumentOrder::Number => order[raw_count..].sort_by_key(|&i| numbers[i]),
+		InstrumentOrder::FirstUse => order[raw_count..].sort_by_key(|&i| first_use[i]),
+	}
+
+	// Note ranges of each instrument, starting with the range for offset 0
+	let mut groups: Vec<Vec<_>> = vec![];
+	for range in &song.note_ranges {
+		match groups.last_mut() {
+			Some(group) if range.offset != 0 => group.push(*range),
+			_ => groups.push(vec![*range]),
+		}
+	}
+	let group_ids: Vec<usize> = groups.iter().map(|group| group.iter().map(|range| range.count as usize).sum()).collect();
+	let group_start = |i: usize| group_ids[..i].iter().sum::<usize>();
+	let mut new_ids = vec![0; group_ids.iter().sum()];
+	let mut note_ranges = vec![];
+	let mut next_id = 0;
+	for &old in &order {
+		let group = groups.get(old).ok_or_else(|| anyhow::anyhow!("Instrument without note range"))?;
+		for id in 0..group_ids[old] {
+			new_ids[group_start(old) + id] = next_id + id;
+		}
+		next_id += group_ids[old];
+		note_ranges.extend_from_slice(group);
+	}
+
+	let tracks = layout.channels.map(|channel| song.tracks[channel].iter().map(|&word| {
+		if word & 0x8000 != 0 {
+			word & !0x1FF | new_ids.get((word & 0x1FF) as usize).copied().unwrap_or(0) as u16
+		} else {
+			word
+		}
+	}).collect());
+	let instruments = order.iter().map(|&i| song.instruments[i]).collect();
+	Ok((SongData { instruments, note_ranges, restart: song.restart, tracks }, order))
+}
+
+/// Bytes of the song data in the layout.
+pub fn layout_data(conversion: &Conversion, layout: &Layout) -> anyhow::Result<Vec<u8>> {
+	let (song, _) = arrange(conversion, layout)?;
+	let mut data = song.write()?;
+	if layout.interleaved {
+		let start = data.len() - song.length() * CHANNEL_COUNT * 2;
+		data.truncate(start);
+		for vblank in 0..song.length() {
+			for track in song.tracks.iter().rev() {
+				data.extend_from_slice(&track[vblank].to_be_bytes());
+			}
+		}
+	}
+	Ok(data)
+}
+
+/// Rearrange the song data of the conversion.
+pub fn apply_layout(conversion: &mut Conversion, layout: &Layout) -> anyhow::Result<()> {
+	if !layout.is_playable() {
+		return Err(anyhow::anyhow!("The player cannot read the layout {}", layout));
+	}
+	let (song, order) = arrange(conversion, layout)?;
+	conversion.song_data = song.write()?;
+	conversion.channels = layout.channels.map(|channel| conversion.channels[channel]);
+	for usage in conversion.instruments.iter_mut().filter_map(|inst| inst.usage.as_mut()) {
+		usage.index = order.iter().position(|&old| old == usage.index).unwrap();
+	}
+	Ok(())
+}
+
+pub struct LayoutScore {
+	pub layout: Layout,
+	pub size: usize,
+	/// Estimated size after LZ compression.
+	pub packed: usize,
+}
+
+/// Estimated packed sizes of all layouts.
+pub fn compare_layouts(conversion: &Conversion) -> anyhow::Result<Vec<LayoutScore>> {
+	Layout::all().into_iter().map(|layout| {
+		let data = layout_data(conversion, &layout)?;
+		Ok(LayoutScore { layout, size: data.len(), packed: estimate_packed_size(&data) })
+	}).collect()
+}
+
+/// The playable layout with the smallest estimated packed size.
+pub fn best_layout(scores: &[LayoutScore]) -> Layout {
+	scores.iter().filter(|score| score.layout.is_playable())
+		.min_by_key(|score| score.packed)
+		.map_or(Layout::DEFAULT, |score| score.layout)
+}
@@ -1,7 +1,27 @@
 
+pub mod aiff;
+pub mod convert;
+pub mod cost;
 pub mod engine;
+pub mod include;
+pub mod layout;
+pub mod module;
+pub mod noteids;
+pub mod pack;
+pub mod paula;
+pub mod refresh;
+pub mod render;
+pub mod replay;
+pub mod sample;
+pub mod song;
+pub mod svx;
+pub mod sync;
+pub mod trim;
+pub mod verify;
+pub mod wav;
 
 use std::collections::VecDeque;
+use std::f32::consts::PI;
 use std::fs::File;
 use std::io::Write;
 use std::path::Path;
@@ -16,7 +36,8 @@ use vst::plugin_main;
 use rfd::FileDialog;
 
 use crate::engine::PARAMETER_COUNT;
-use crate::engine::{CinterEngine, CinterInstrument};
+use crate::engine::{CinterEngine, CinterInstrument, CinterParams, SynthVersion};
+use crate::paula::{period_for_key, Amiga, AmigaModel, PaulaVoice};
 
 #[allow(dead_code)]
 pub enum MidiCommand {
@@ -47,23 +68,77 @@ pub struct TimedMidiCommand {
 	command: MidiCommand,
 }
 
+/// How notes resample the instrument in previews.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+	Nearest,
+	Linear,
+	Cubic,
+	/// Windowed sinc, band-limited to the output sample rate.
+	Sinc,
+	/// Protracker periods played through a model of Paula and the output filters.
+	Paula(Amiga),
+}
+
+impl Interpolation {
+	pub const ALL: [Interpolation; 8] = [
+		Interpolation::Nearest,
+		Interpolation::Linear,
+		Interpolation::Cubic,
+		Interpolation::Sinc,
+		Interpolation::Paula(Amiga { model: AmigaModel::A500, led_filter: false }),
+		Interpolation::Paula(Amiga { model: AmigaModel::A500, led_filter: true }),
+		Interpolation::Paula(Amiga { model: AmigaModel::A1200, led_filter: false }),
+		Interpolation::Paula(Amiga { model: AmigaModel::A1200, led_filter: true }),
+	];
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			Interpolation::Nearest => "Nearest",
+			Interpolation::Linear => "Linear",
+			Interpolation::Cubic => "Cubic",
+			Interpolation::Sinc => "Sinc",
+			Interpolation::Paula(Amiga { model: AmigaModel::A500, led_filter: false }) => "Paula A500",
+			Interpolation::Paula(Amiga { model: AmigaModel::A500, led_filter: true }) => "Paula A500 LED",
+			Interpolation::Paula(Amiga { model: AmigaModel::A1200, led_filter: false }) => "Paula A1200",
+			Interpolation::Paula(Amiga { model: AmigaModel::A1200, led_filter: true }) => "Paula A1200 LED",
+		}
+	}
+}
+
+// Zero crossings on each side of the sinc kernel, at the lower of the two sample rates
+const SINC_ZERO_CROSSINGS: usize = 8;
+// Limit on the kernel width when playing far above the output sample rate
+const SINC_MAX_STEP: f32 = 16.0;
+
 pub struct Note {
 	instrument: Arc<RwLock<CinterInstrument>>,
 
 	time: usize,
 	pub key: u8,
 	freq: f32,
+	interpolation: Interpolation,
+	paula: Option<PaulaVoice>,
 
 	release_time: Option<usize>
 }
 
 impl Note {
-	pub fn new(instrument: Arc<RwLock<CinterInstrument>>, key: u8, _velocity: u8, sample_rate: f32) -> Note {
+	pub fn new(instrument: Arc<RwLock<CinterInstrument>>, key: u8, velocity: u8, sample_rate: f32) -> Note {
+		Self::with_interpolation(instrument, key, velocity, sample_rate, Interpolation::Cubic)
+	}
+
+	pub fn with_interpolation(instrument: Arc<RwLock<CinterInstrument>>, key: u8, _velocity: u8, sample_rate: f32, interpolation: Interpolation) -> Note {
 		Note {
 			instrument: instrument.clone(),
 			time: 0,
 			key,
 			freq: 440.0 * ((key + 27) as f32 / 12.0).exp2() / sample_rate,
+			interpolation,
+			paula: match interpolation {
+				Interpolation::Paula(amiga) => Some(PaulaVoice::new(period_for_key(key), sample_rate, Some(amiga))),
+				_ => None,
+			},
 
 			release_time: None
 		}
@@ -77,25 +152,55 @@ impl Note {
 	}
 
 	pub fn produce_sample(&mut self) -> f32 {
+		let mut instrument = self.instrument.write().unwrap();
 		let phase = self.time as f32 * self.freq;
 		let i = phase.floor() as usize;
 		let t = phase - i as f32;
-		let a0 = t*((2.0-t)*t-1.0);
-		let a1 = t*t*(3.0*t-5.0)+2.0;
-		let a2 = t*((4.0-3.0*t)*t+1.0);
-		let a3 = t*t*(t-1.0);
-		let mut instrument = self.instrument.write().unwrap();
-		let d0 = instrument.get_sample(i) as f32;
-		let d1 = instrument.get_sample(i + 1) as f32;
-		let d2 = instrument.get_sample(i + 2) as f32;
-		let d3 = instrument.get_sample(i + 3) as f32;
-		let mut v = a0*d0 + a1*d1 + a2*d2 + a3*d3;
+		// Interpolation is between samples i + 1 and i + 2.
+		let mut d = |offset: isize| match i as isize + offset {
+			index if index >= 0 => instrument.get_sample(index as usize) as f32,
+			_ => 0.0,
+		};
+		let mut v = match self.interpolation {
+			Interpolation::Nearest => d(if t < 0.5 { 1 } else { 2 }),
+			Interpolation::Linear => {
+				let (d1, d2) = (d(1), d(2));
+				d1 + (d2 - d1) * t
+			},
+			Interpolation::Cubic => {
+				let a0 = t*((2.0-t)*t-1.0);
+				let a1 = t*t*(3.0*t-5.0)+2.0;
+				let a2 = t*((4.0-3.0*t)*t+1.0);
+				let a3 = t*t*(t-1.0);
+				(a0*d(0) + a1*d(1) + a2*d(2) + a3*d(3)) * 0.5
+			},
+			Interpolation::Sinc => {
+				// Lower the cutoff when skipping samples, to stay below the output Nyquist frequency.
+				let scale = 1.0 / self.freq.clamp(1.0, SINC_MAX_STEP);
+				let width = (SINC_ZERO_CROSSINGS as f32 / scale).ceil() as isize;
+				let mut sum = 0.0;
+				for k in 1 - width ..= width {
+					let x = (k as f32 - t) * scale;
+					let window = 0.42 + 0.5 * (PI * x / SINC_ZERO_CROSSINGS as f32).cos() + 0.08 * (2.0 * PI * x / SINC_ZERO_CROSSINGS as f32).cos();
+					let sinc = if x == 0.0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+					sum += d(k + 1) * sinc * window.max(0.0);
+				}
+				sum * scale
+			},
+			Interpolation::Paula(..) => {
+				let paula = self.paula.as_mut().unwrap();
+				paula.produce_sample(|i| instrument.get_sample(i))
+			},
+		};
 		v *= self.release_amplitude();
 		self.time += 1;
-		v / 254.0
+		v / 127.0
 	}
 
 	pub fn current_index(&self) -> usize {
+		if let Some(paula) = &self.paula {
+			return paula.current_index();
+		}
 		let phase = self.time as f32 * self.freq;
 		phase.floor() as usize
 	}
@@ -131,21 +236,29 @@ pub struct CinterParameterObject {
 }
 
 struct Parameters {
-	values: [f32; PARAMETER_COUNT],
+	values: CinterParams,
+	// Index into Interpolation::ALL, as a normalized parameter after the synth parameters
+	interpolation: f32,
 	changed: bool,
 }
 
+impl Parameters {
+	fn interpolation(&self) -> Interpolation {
+		let last = Interpolation::ALL.len() - 1;
+		Interpolation::ALL[((self.interpolation * last as f32).round() as usize).min(last)]
+	}
+}
+
 impl Default for CinterPlugin {
 	fn default() -> Self {
 		let params = Parameters {
-			values: [
-				0.05, 0.40, 0.53, 0.50, 0.65, 0.50, 0.20, 0.40, 0.0, 0.0, 0.1, 0.2
-			],
+			values: CinterParams::default(),
+			interpolation: 2.0 / 7.0, // Cubic
 			changed: false,
 		};
 		let engine = Arc::new(CinterEngine::new());
 		let instrument = Arc::new(RwLock::new(CinterInstrument::new(
-			engine.clone(), &params.values, &[], None, None
+			engine.clone(), SynthVersion::Cinter4, &params.values, &[], None, None
 		)));
 
 		CinterPlugin {
@@ -172,7 +285,7 @@ impl Plugin for CinterPlugin {
 	fn get_info(&self) -> Info {
 		Info {
 			presets: 1,
-			parameters: PARAMETER_COUNT as i32,
+			parameters: PARAMETER_COUNT as i32 + 1,
 			inputs: 0,
 			outputs: 2,
 			category: Category::Synth,
@@ -234,27 +347,43 @@ impl Plugin for CinterPlugin {
 
 impl PluginParameters for CinterParameterObject {
 	fn get_parameter_name(&self, index: i32) -> String {
+		if index as usize == PARAMETER_COUNT {
+			return "interpolation".to_string();
+		}
 		CinterEngine::get_parameter_name(index)
 	}
 
 	fn get_parameter_text(&self, index: i32) -> String {
 		let params = self.params.read().unwrap();
-		CinterEngine::get_parameter_text_and_label(index, params.values[index as usize]).0
+		if index as usize == PARAMETER_COUNT {
+			return params.interpolation().name().to_string();
+		}
+		CinterEngine::get_parameter_text_and_label(SynthVersion::Cinter4, index, params.values.normalized(index as usize)).0
 	}
 
 	fn get_parameter_label(&self, index: i32) -> String {
 		let params = self.params.read().unwrap();
-		CinterEngine::get_parameter_text_and_label(index, params.values[index as usize]).1
+		if index as usize == PARAMETER_COUNT {
+			return String::new();
+		}
+		CinterEngine::get_parameter_text_and_label(SynthVersion::Cinter4, index, params.values.normalized(index as usize)).1
 	}
 
 	fn get_parameter(&self, index: i32) -> f32 {
 		let params = self.params.read().unwrap();
-		params.values[index as usize]
+		if index as usize == PARAMETER_COUNT {
+			return params.interpolation;
+		}
+		params.values.normalized(index as usize)
 	}
 
 	fn set_parameter(&self, index: i32, value: f32) {
 		let mut params = self.params.write().unwrap();
-		params.values[index as usize] = value;
+		if index as usize == PARAMETER_COUNT {
+			params.interpolation = value;
+			return;
+		}
+		params.values.set_normalized(index as usize, value);
 		params.changed = true;
 	}
 
@@ -265,7 +394,7 @@ impl PluginParameters for CinterParameterObject {
 	fn get_preset_data(&self) -> Vec<u8> {
 		let params = self.params.read().unwrap();
 		let mut data = vec![];
-		for p in &params.values {
+		for p in params.values.to_normalized().iter().chain([&params.interpolation]) {
 			data.extend_from_slice(&p.to_bits().to_le_bytes());
 		}
 		data
@@ -280,7 +409,13 @@ impl PluginParameters for CinterParameterObject {
 		for (i, chunk) in data.chunks_exact(4).enumerate() {
 			let mut bytes = [0u8; 4];
 			bytes.copy_from_slice(chunk);
-			params.values[i] = f32::from_bits(u32::from_le_bytes(bytes));
+			let value = f32::from_bits(u32::from_le_bytes(bytes));
+			match i {
+				i if i < PARAMETER_COUNT => params.values.set_normalized(i, value),
+				// Missing from presets saved before the interpolation parameter was added
+				PARAMETER_COUNT => params.interpolation = value,
+				_ => {}
+			}
 		}
 		params.changed = true;
 	}
@@ -299,14 +434,14 @@ impl CinterPlugin {
 				let mut params = self.param_object.params.write().unwrap();
 				if params.changed {
 					self.instrument = Arc::new(RwLock::new(CinterInstrument::new(
-						self.engine.clone(), &params.values, &[], None, None
+						self.engine.clone(), SynthVersion::Cinter4, &params.values, &[], None, None
 					)));
 					params.changed = false;
 				}
-				self.notes.push(Note::new(self.instrument.clone(), key, velocity, self.sample_rate));
+				self.notes.push(Note::with_interpolation(self.instrument.clone(), key, velocity, self.sample_rate, params.interpolation()));
 
 				if key == 52 {
-					write_filename = Some(CinterEngine::sample_filename_from_parameters(&params.values));
+					write_filename = Some(CinterEngine::sample_filename_from_parameters(SynthVersion::Cinter4, &params.values));
 				}
 			},
 			MidiCommand::NoteOff { key, velocity, .. } => {
@@ -0,0 +1,274 @@
+use crate::sample::SampleFile;
+
+pub const INSTRUMENT_COUNT: usize = 31;
+/// Longest sample in bytes, as the length is stored in words in a 16-bit field.
+pub const MAX_SAMPLE_LENGTH: usize = 0x1FFFE;
+pub const CHANNEL_COUNT: usize = 4;
+pub const ROW_COUNT: usize = 64;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct TrackRow {
+	pub period: u16,
+	pub instrument: u8,
+	pub command: u8,
+	pub argument: u8,
+}
+
+#[derive(Clone)]
+pub struct Pattern {
+	pub rows: Vec<[TrackRow; CHANNEL_COUNT]>,
+}
+
+#[derive(Clone, Default)]
+pub struct Instrument {
+	name: [u8; 22],
+	pub finetune: u8,
+	pub volume: u8,
+	/// Repeat offset in words.
+	pub repeat_offset: u16,
+	/// Repeat length in words.
+	pub repeat_length: u16,
+	pub samples: Vec<i8>,
+}
+
+#[derive(Clone)]
+pub struct Module {
+	title: [u8; 20],
+	/// Instrument number n is found at index n - 1.
+	pub instruments: Vec<Instrument>,
+	pub song_length: u8,
+	pub restart: u8,
+	pub positions: [u8; 128],
+	tag: [u8; 4],
+	pub patterns: Vec<Pattern>,
+	/// Data following the last sample, kept to write the file back unchanged.
+	pub trailing: Vec<u8>,
+}
+
+struct ModReader<'d> {
+	data: &'d [u8],
+	pos: usize,
+}
+
+impl<'d> ModReader<'d> {
+	fn read(&mut self, count: usize, what: &str) -> anyhow::Result<&'d [u8]> {
+		let bytes = self.data.get(self.pos .. self.pos + count)
+			.ok_or_else(|| anyhow::anyhow!("Module truncated in {}", what))?;
+		self.pos += count;
+		Ok(bytes)
+	}
+
+	fn read_u8(&mut self, what: &str) -> anyhow::Result<u8> {
+		Ok(self.read(1, what)?[0])
+	}
+
+	fn read_u16(&mut self, what: &str) -> anyhow::Result<u16> {
+		Ok(u16::from_be_bytes(self.read(2, what)?.try_into()?))
+	}
+}
+
+fn latin1_string(bytes: &[u8]) -> String {
+	let s: String = bytes.iter().map(|&b| b as char).collect();
+	s.trim_end_matches('\0').to_string()
+}
+
+fn latin1_bytes<const N: usize>(s: &str) -> [u8; N] {
+	let mut bytes = [0; N];
+	for (b, c) in bytes.iter_mut().zip(s.chars()) {
+		*b = if (c as u32) < 256 { c as u8 } else { b'?' };
+	}
+	bytes
+}
+
+impl TrackRow {
+	fn read(r: &mut ModReader) -> anyhow::Result<TrackRow> {
+		let bytes = r.read(4, "pattern data")?;
+		Ok(TrackRow {
+			period: u16::from_be_bytes([bytes[0] & 0x0F, bytes[1]]),
+			instrument: (bytes[0] & 0xF0) | (bytes[2] >> 4),
+			command: bytes[2] & 0x0F,
+			argument: bytes[3],
+		})
+	}
+
+	fn write(&self, data: &mut Vec<u8>) {
+		data.push((self.instrument & 0xF0) | (self.period >> 8) as u8 & 0x0F);
+		data.push(self.period as u8);
+		data.push((self.instrument << 4) | (self.command & 0x0F));
+		data.push(self.argument);
+	}
+
+	/// Nearest note (0 = C-1) for the period, or None if there is no note.
+	pub fn note(&self) -> Option<i32> {
+		if self.period > 0 {
+			Some(((856.0 / self.period as f64).log2() * 12.0).round() as i32)
+		} else {
+			None
+		}
+	}
+}
+
+impl Instrument {
+	fn read(r: &mut ModReader) -> anyhow::Result<(Instrument, usize)> {
+		let what = "instrument headers";
+		let name = r.read(22, what)?.try_into()?;
+		let length = r.read_u16(what)? as usize;
+		let instrument = Instrument {
+			name,
+			finetune: r.read_u8(what)?,
+			volume: r.read_u8(what)?,
+			repeat_offset: r.read_u16(what)?,
+			repeat_length: r.read_u16(what)?,
+			samples: vec![],
+		};
+		Ok((instrument, length))
+	}
+
+	fn write(&self, data: &mut Vec<u8>) {
+		data.extend_from_slice(&self.name);
+		data.extend_from_slice(&((self.stored_samples().len() / 2) as u16).to_be_bytes());
+		data.push(self.finetune);
+		data.push(self.volume);
+		data.extend_from_slice(&self.repeat_offset.to_be_bytes());
+		data.extend_from_slice(&self.repeat_length.to_be_bytes());
+	}
+
+	pub fn name(&self) -> String {
+		latin1_string(&self.name)
+	}
+
+	pub fn set_name(&mut self, name: &str) {
+		self.name = latin1_bytes(name);
+	}
+
+	/// Sample length in words.
+	pub fn length(&self) -> usize {
+		self.samples.len() / 2
+	}
+
+	// Samples written to a module, which cannot hold more than the maximum length.
+	fn stored_samples(&self) -> &[i8] {
+		&self.samples[.. self.samples.len().min(MAX_SAMPLE_LENGTH)]
+	}
+
+	/// Name, sample data and repeat, where the repeat is taken to last until the end.
+	pub fn to_sample(&self) -> SampleFile {
+		let name = self.name();
+		let repeat_length = if self.repeat_length > 1 {
+			self.samples.len().saturating_sub(self.repeat_offset as usize * 2)
+		} else {
+			0
+		};
+		SampleFile {
+			name: (!name.is_empty()).then_some(name),
+			data: self.samples.clone(),
+			repeat_length,
+		}
+	}
+
+	/// Replace the name and sample data, padded to a whole number of words,
+	/// keeping volume and finetune.
+	pub fn set_sample(&mut self, sample: &SampleFile) -> anyhow::Result<()> {
+		if sample.data.len() > MAX_SAMPLE_LENGTH {
+			return Err(anyhow::anyhow!("Sample of {} bytes is longer than the {} bytes a module can hold",
+				sample.data.len(), MAX_SAMPLE_LENGTH));
+		}
+		self.set_name(sample.name.as_deref().unwrap_or(""));
+		self.samples = sample.data.clone();
+		if self.samples.len() & 1 != 0 {
+			self.samples.push(0);
+		}
+		if sample.repeat_length >= 2 {
+			self.repeat_offset = ((self.samples.len() - sample.repeat_length) / 2) as u16;
+			self.repeat_length = ((self.samples.len() / 2) - self.repeat_offset as usize) as u16;
+		} else {
+			self.repeat_offset = 0;
+			self.repeat_length = 1;
+		}
+		Ok(())
+	}
+}
+
+impl Module {
+	pub fn read(data: &[u8]) -> anyhow::Result<Module> {
+		let r = &mut ModReader { data, pos: 0 };
+		let title = r.read(20, "module title")?.try_into()?;
+		let mut instruments = vec![];
+		let mut lengths = vec![];
+		for _ in 0..INSTRUMENT_COUNT {
+			let (instrument, length) = Instrument::read(r)?;
+			instruments.push(instrument);
+			lengths.push(length);
+		}
+		let song_length = r.read_u8("position list")?;
+		let restart = r.read_u8("position list")?;
+		let positions: [u8; 128] = r.read(128, "position list")?.try_into()?;
+		let tag: [u8; 4] = r.read(4, "module format tag")?.try_into()?;
+		if !matches!(&tag, b"M.K." | b"M!K!" | b"FLT4" | b"4CHN") {
+			return Err(anyhow::anyhow!("Not a 31-instrument, 4-channel module"));
+		}
+
+		let pattern_count = *positions.iter().max().unwrap() as usize + 1;
+		let mut patterns = vec![];
+		for _ in 0..pattern_count {
+			let mut rows = vec![];
+			for _ in 0..ROW_COUNT {
+				let mut row = [TrackRow::default(); CHANNEL_COUNT];
+				for track_row in &mut row {
+					*track_row = TrackRow::read(r)?;
+				}
+				rows.push(row);
+			}
+			patterns.push(Pattern { rows });
+		}
+
+		for (i, (instrument, length)) in instruments.iter_mut().zip(lengths).enumerate() {
+			let samples = r.read(length * 2, &format!("sample data of instrument {}", i + 1))?;
+			instrument.samples = samples.iter().map(|&s| s as i8).collect();
+		}
+		let trailing = data[r.pos ..].to_vec();
+
+		Ok(Module { title, instruments, song_length, restart, positions, tag, patterns, trailing })
+	}
+
+	pub fn write(&self) -> Vec<u8> {
+		let mut data = vec![];
+		data.extend_from_slice(&self.title);
+		for instrument in &self.instruments {
+			instrument.write(&mut data);
+		}
+		data.push(self.song_length);
+		data.push(self.restart);
+		data.extend_from_slice(&self.positions);
+		data.extend_from_slice(&self.tag);
+		for pattern in &self.patterns {
+			for row in &pattern.rows {
+				for track_row in row {
+					track_row.write(&mut data);
+				}
+			}
+		}
+		for instrument in &self.instruments {
+			data.extend(instrument.stored_samples().iter().map(|&s| s as u8));
+		}
+		data.extend_from_slice(&self.trailing);
+		data
+	}
+
+	pub fn title(&self) -> String {
+		latin1_string(&self.title)
+	}
+
+	pub fn set_title(&mut self, title: &str) {
+		self.title = latin1_bytes(title);
+	}
+
+	/// Instrument by its number (1-31) as used in pattern data.
+	pub fn instrument(&self, number: usize) -> &Instrument {
+		&self.instruments[number - 1]
+	}
+
+	pub fn instrument_mut(&mut self, number: usize) -> &mut Instrument {
+		&mut self.instruments[number - 1]
+	}
+}
@@ -0,0 +1,232 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::convert::{notename, Conversion, PERIOD_TABLE};
+use crate::engine::{CinterEngine, SynthVersion, WordParameters};
+use crate::module::{Instrument, Module};
+use crate::song::SongData;
+
+// Largest 9xx distance for which offsets are suggested to share a range
+const MAX_OFFSET_DISTANCE: u8 = 2;
+
+/// Notes played by an instrument from a sample offset, each needing a note ID.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IdRange {
+	/// Module instrument number.
+	pub instrument: usize,
+	/// Sample offset as the 9xx argument.
+	pub offset: u8,
+	pub note_min: u8,
+	pub note_max: u8,
+}
+
+impl IdRange {
+	pub fn ids(&self) -> usize {
+		(self.note_max - self.note_min) as usize + 1
+	}
+}
+
+impl fmt::Display for IdRange {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "Instrument {:02} offset {:02X}: {}-{} ({} IDs)", self.instrument, self.offset,
+			notename(Some(self.note_min as i32)), notename(Some(self.note_max as i32)), self.ids())
+	}
+}
+
+/// Note ranges of the converted song, in the order of the song data.
+pub fn id_ranges(conversion: &Conversion) -> anyhow::Result<Vec<IdRange>> {
+	let song = SongData::read(&conversion.song_data)?;
+	let numbers = conversion.instrument_numbers();
+	let mut ranges = vec![];
+	let mut index = None;
+	for range in &song.note_ranges {
+		if range.offset == 0 {
+			index = Some(index.map_or(0, |i| i + 1));
+		}
+		let instrument = index.and_then(|i| numbers.get(i)).copied()
+			.ok_or_else(|| anyhow::anyhow!("Note range without instrument"))?;
+		ranges.push(IdRange {
+			instrument,
+			offset: (range.offset / 128) as u8,
+			note_min: range.note_min,
+			note_max: range.note_min + range.count - 1,
+		});
+	}
+	Ok(ranges)
+}
+
+pub fn id_count(ranges: &[IdRange]) -> usize {
+	ranges.iter().map(|range| range.ids()).sum()
+}
+
+/// Change to the module which lowers the number of note IDs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fix {
+	/// Play the notes of an instrument with an identical instrument.
+	Merge { from: usize, into: usize },
+	/// Play the notes of an instrument with a nearby 9xx offset, which changes the sound.
+	CollapseOffset { instrument: usize, from: u8, into: u8 },
+	/// Play the notes of a raw instrument an octave up with the instrument it
+	/// is a downsampled copy of, which changes the sound slightly.
+	Transpose { from: usize, into: usize },
+}
+
+impl Fix {
+	/// Whether the fix keeps the sound, so it can be applied automatically.
+	pub fn is_exact(&self) -> bool {
+		matches!(self, Fix::Merge { .. })
+	}
+
+	/// Note ranges after the fix.
+	pub fn apply_to_ranges(&self, ranges: &[IdRange]) -> Vec<IdRange> {
+		let mut merged: BTreeMap<(usize, u8), (u8, u8)> = BTreeMap::new();
+		let mut order = vec![];
+		for range in ranges {
+			let mut range = *range;
+			match *self {
+				Fix::Merge { from, into } if range.instrument == from => {
+					range.instrument = into;
+				},
+				Fix::CollapseOffset { instrument, from, into } if range.instrument == instrument && range.offset == from => {
+					range.offset = into;
+				},
+				Fix::Transpose { from, into } if range.instrument == from => {
+					range.instrument = into;
+					range.note_min += 12;
+					range.note_max += 12;
+				},
+				_ => {},
+			}
+			let key = (range.instrument, range.offset);
+			let entry = merged.entry(key).or_insert_with(|| {
+				order.push(key);
+				(range.note_min, range.note_max)
+			});
+			*entry = (entry.0.min(range.note_min), entry.1.max(range.note_max));
+		}
+		order.iter().map(|&(instrument, offset)| {
+			let (note_min, note_max) = merged[&(instrument, offset)];
+			IdRange { instrument, offset, note_min, note_max }
+		}).collect()
+	}
+
+	/// Change the pattern data to play the notes of the merged instrument with
+	/// the other one. Only exact fixes can be applied.
+	pub fn apply(&self, module: &mut Module) {
+		if let Fix::Merge { from, into } = *self {
+			for pattern in &mut module.patterns {
+				for track_row in pattern.rows.iter_mut().flatten() {
+					if track_row.instrument as usize == from {
+						track_row.instrument = into as u8;
+					}
+				}
+			}
+		}
+	}
+}
+
+impl fmt::Display for Fix {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match *self {
+			Fix::Merge { from, into } =>
+				write!(f, "Instrument {:02} is identical to instrument {:02}; use {:02} instead", from, into, into),
+			Fix::CollapseOffset { instrument, from, into } =>
+				write!(f, "Instrument {:02}: use offset 9{:02X} instead of 9{:02X}", instrument, into, from),
+			Fix::Transpose { from, into } =>
+				write!(f, "Instrument {:02} is instrument {:02} an octave down; play {:02} an octave up instead", from, into, into),
+		}
+	}
+}
+
+pub struct Suggestion {
+	pub fix: Fix,
+	/// Number of note IDs saved.
+	pub saved: usize,
+}
+
+// Parameters the converter generates a Cinter instrument from.
+fn word_parameters(inst: &Instrument) -> Option<WordParameters> {
+	let name = inst.name();
+	let params = CinterEngine::parameters_from_sample_filename(&name).ok()?;
+	Some(WordParameters::from_params(SynthVersion::from_sample_filename(&name), &params))
+}
+
+// Same sound when converted. Cinter instruments are generated from their
+// names, so only the sample data of raw instruments is compared.
+fn identical(a: &Instrument, b: &Instrument) -> bool {
+	let same_sound = match (word_parameters(a), word_parameters(b)) {
+		(Some(pa), Some(pb)) => pa == pb && a.length() == b.length(),
+		(None, None) => a.samples == b.samples,
+		_ => false,
+	};
+	same_sound && a.volume == b.volume && a.finetune == b.finetune &&
+		a.repeat_offset == b.repeat_offset && a.repeat_length == b.repeat_length
+}
+
+// Whether b holds every other sample of a, with the repeat halved.
+fn octave_below(a: &Instrument, b: &Instrument) -> bool {
+	let halved = |x: u16| x / 2;
+	let repeat = |i: &Instrument| if i.repeat_length > 1 { Some((i.repeat_offset, i.repeat_length)) } else { None };
+	word_parameters(a).is_none() && word_parameters(b).is_none() &&
+		!b.samples.is_empty() && a.volume == b.volume && a.finetune == 0 && b.finetune == 0 &&
+		a.samples.iter().step_by(2).take(b.samples.len()).eq(&b.samples) &&
+		b.samples.len() + 1 >= a.samples.len() / 2 &&
+		repeat(b) == repeat(a).map(|(offset, length)| (halved(offset), halved(length)))
+}
+
+/// Fixes that lower the number of note IDs, with the largest savings first.
+pub fn suggestions(module: &Module, ranges: &[IdRange]) -> Vec<Suggestion> {
+	let mut used: Vec<usize> = ranges.iter().map(|range| range.instrument).collect();
+	used.dedup();
+	let mut fixes = vec![];
+	for (i, &into) in used.iter().enumerate() {
+		for &from in &used[i + 1 ..] {
+			if identical(module.instrument(into), module.instrument(from)) {
+				fixes.push(Fix::Merge { from, into });
+			}
+		}
+		for &from in &used {
+			let fits = ranges.iter().filter(|range| range.instrument == from)
+				.all(|range| range.offset == 0 && (range.note_max as usize) + 12 < PERIOD_TABLE.len());
+			if from != into && fits && octave_below(module.instrument(into), module.instrument(from)) {
+				fixes.push(Fix::Transpose { from, into });
+			}
+		}
+		let mut offsets: Vec<u8> = ranges.iter().filter(|range| range.instrument == into).map(|range| range.offset).collect();
+		offsets.sort_unstable();
+		for pair in offsets.windows(2) {
+			if pair[1] - pair[0] <= MAX_OFFSET_DISTANCE {
+				fixes.push(Fix::CollapseOffset { instrument: into, from: pair[1], into: pair[0] });
+			}
+		}
+	}
+
+	let count = id_count(ranges);
+	let mut suggestions: Vec<Suggestion> = fixes.into_iter().filter_map(|fix| {
+		let saved = count.checked_sub(id_count(&fix.apply_to_ranges(ranges)))?;
+		(saved > 0).then_some(Suggestion { fix, saved })
+	}).collect();
+	suggestions.sort_by_key(|s| std::cmp::Reverse(s.saved));
+	suggestions
+}
+
+/// Apply the exact fixes one by one as long as they lower the number of note
+/// IDs, returning those applied.
+pub fn apply_exact_fixes(module: &mut Module, ranges: &[IdRange]) -> Vec<Suggestion> {
+	let mut ranges = ranges.to_vec();
+	let mut applied = vec![];
+	while let Some(suggestion) = suggestions(module, &ranges).into_iter().find(|s| s.fix.is_exact()) {
+		suggestion.fix.apply(module);
+		ranges = suggestion.fix.apply_to_ranges(&ranges);
+		applied.push(suggestion);
+	}
+	applied
+}
+
+/// Note ranges needing the most IDs, for reporting.
+pub fn largest_ranges(ranges: &[IdRange], count: usize) -> Vec<IdRange> {
+	let mut ranges = ranges.to_vec();
+	ranges.sort_by_key(|range| std::cmp::Reverse(range.ids()));
+	ranges.truncate(count);
+	ranges
+}
@@ -0,0 +1,61 @@
+// Estimate of the size of data packed by an LZ-style cruncher, for comparing
+// alternative encodings of the same data rather than predicting exact sizes.
+
+const MIN_MATCH: usize = 2;
+const MAX_CANDIDATES: usize = 256;
+const WINDOW: usize = 1 << 16;
+
+// Bits of an Elias gamma code for n >= 1
+fn gamma_bits(n: usize) -> f64 {
+	(2 * (usize::BITS - 1 - n.leading_zeros()) + 1) as f64
+}
+
+/// Estimated packed size in bytes. Literals cost their order-0 entropy,
+/// matches cost gamma codes for length and offset, plus one flag bit each.
+pub fn estimate_packed_size(data: &[u8]) -> usize {
+	let mut counts = [0usize; 256];
+	data.iter().for_each(|&b| counts[b as usize] += 1);
+	let literal_bits: Vec<f64> = counts.iter()
+		.map(|&c| 1.0 + (data.len() as f64 / c.max(1) as f64).log2())
+		.collect();
+
+	// Previous positions with the same two bytes
+	let mut last: Vec<Option<usize>> = vec![None; 1 << 16];
+	let mut previous: Vec<Option<usize>> = vec![None; data.len()];
+	let key = |i: usize| (data[i] as usize) << 8 | data[i + 1] as usize;
+
+	let mut bits = 0.0;
+	let mut pos = 0;
+	let mut inserted = 0;
+	while pos < data.len() {
+		while inserted < pos && inserted + 1 < data.len() {
+			previous[inserted] = last[key(inserted)];
+			last[key(inserted)] = Some(inserted);
+			inserted += 1;
+		}
+		// Longest match, preferring the nearest
+		let mut best = (0, 0);
+		if pos + 1 < data.len() {
+			let mut candidate = last[key(pos)];
+			for _ in 0..MAX_CANDIDATES {
+				let Some(start) = candidate.filter(|&start| pos - start <= WINDOW) else { break };
+				let length = data[start..].iter().zip(&data[pos..]).take_while(|(a, b)| a == b).count();
+				if length > best.0 {
+					best = (length, pos - start);
+				}
+				candidate = previous[start];
+			}
+		}
+		let (length, offset) = best;
+		let match_bits = |length: usize| 1.0 + gamma_bits(length - MIN_MATCH + 1) + gamma_bits(offset);
+		let literals_bits: f64 = data[pos .. pos + length].iter().map(|&b| literal_bits[b as usize]).sum();
+		if length >= MIN_MATCH && match_bits(length) < literals_bits {
+			bits += match_bits(length);
+			pos += length;
+		} else {
+			bits += literal_bits[data[pos] as usize];
+			pos += 1;
+		}
+	}
+	(bits / 8.0).ceil() as usize
+}
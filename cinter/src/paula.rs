@@ -0,0 +1,194 @@
+use std::f64::consts::PI;
+use std::sync::OnceLock;
+
+use crate::convert::PERIOD_TABLE;
+use crate::render::PAULA_CLOCK;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AmigaModel {
+	/// 4.4kHz RC low-pass filter on the output.
+	A500,
+	/// 34kHz RC low-pass filter on the output.
+	A1200,
+}
+
+/// Analog output stage of an Amiga.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Amiga {
+	pub model: AmigaModel,
+	/// The 3.1kHz "LED" filter, controlled by the power LED bit.
+	pub led_filter: bool,
+}
+
+/// Protracker period for a key, where key 12 is C-1. Keys outside the three
+/// Protracker octaves continue the table by octaves.
+pub fn period_for_key(key: u8) -> u16 {
+	let octave = key as i32 / 12 - 1;
+	let period = PERIOD_TABLE[key as usize % 12 + octave.clamp(0, 2) as usize * 12] as f64;
+	let period = period * (-(octave - octave.clamp(0, 2)) as f64).exp2();
+	period.round().clamp(1.0, 65535.0) as u16
+}
+
+// Band-limited step, as zero crossings on each side and table steps per output sample.
+const BLEP_ZERO_CROSSINGS: usize = 8;
+const BLEP_OVERSAMPLING: usize = 64;
+const BLEP_BUFFER_SIZE: usize = BLEP_ZERO_CROSSINGS * 2 + 2;
+
+// Difference between a Blackman-windowed band-limited step and the ideal step,
+// from -BLEP_ZERO_CROSSINGS to BLEP_ZERO_CROSSINGS output samples around the step.
+fn blep_table() -> &'static [f32] {
+	static TABLE: OnceLock<Vec<f32>> = OnceLock::new();
+	TABLE.get_or_init(|| {
+		let size = BLEP_ZERO_CROSSINGS * 2 * BLEP_OVERSAMPLING + 1;
+		let t = |i: usize| (i as f64 - (BLEP_ZERO_CROSSINGS * BLEP_OVERSAMPLING) as f64) / BLEP_OVERSAMPLING as f64;
+		let impulse: Vec<f64> = (0..size).map(|i| {
+			let t = t(i);
+			let sinc = if t == 0.0 { 1.0 } else { (PI * t).sin() / (PI * t) };
+			let x = t / BLEP_ZERO_CROSSINGS as f64;
+			sinc * (0.42 + 0.5 * (PI * x).cos() + 0.08 * (2.0 * PI * x).cos())
+		}).collect();
+		let mut step = vec![0.0; size];
+		for i in 1..size {
+			step[i] = step[i - 1] + (impulse[i - 1] + impulse[i]) * 0.5;
+		}
+		let total = step[size - 1];
+		(0..size).map(|i| {
+			let ideal = if t(i) >= 0.0 { 1.0 } else { 0.0 };
+			(step[i] / total - ideal) as f32
+		}).collect()
+	})
+}
+
+fn blep_residual(t: f64) -> f32 {
+	let table = blep_table();
+	let pos = (t + BLEP_ZERO_CROSSINGS as f64) * BLEP_OVERSAMPLING as f64;
+	if pos < 0.0 || pos >= (table.len() - 1) as f64 {
+		return 0.0;
+	}
+	let i = pos as usize;
+	let frac = (pos - i as f64) as f32;
+	table[i] + (table[i + 1] - table[i]) * frac
+}
+
+fn one_pole_coefficient(cutoff: f64, sample_rate: f64) -> f32 {
+	(1.0 - (-2.0 * PI * cutoff / sample_rate).exp()) as f32
+}
+
+/// RC filters and LED filter between Paula and the audio output.
+#[derive(Clone)]
+pub struct OutputFilter {
+	low_pass: f32,
+	low_pass_state: f32,
+	high_pass: f32,
+	high_pass_state: f32,
+	// Biquad coefficients and state for the LED filter
+	led: Option<[f32; 5]>,
+	led_state: [f32; 4],
+}
+
+impl OutputFilter {
+	pub fn new(amiga: Amiga, sample_rate: f32) -> OutputFilter {
+		let sample_rate = sample_rate as f64;
+		let rc = |r: f64, c: f64| 1.0 / (2.0 * PI * r * c);
+		let low_pass_cutoff = match amiga.model {
+			AmigaModel::A500 => rc(360.0, 0.1e-6),
+			AmigaModel::A1200 => rc(680.0, 6800e-12),
+		};
+		// Sallen-Key filter with R1 = R2 = 10k, C1 = 6800p, C2 = 3900p
+		let led = amiga.led_filter.then(|| {
+			let (r, c1, c2): (f64, f64, f64) = (10000.0, 6800e-12, 3900e-12);
+			let cutoff = 1.0 / (2.0 * PI * r * (c1 * c2).sqrt());
+			let q = (c1 * c2).sqrt() / (2.0 * c2);
+			let w = 2.0 * PI * cutoff / sample_rate;
+			let alpha = w.sin() / (2.0 * q);
+			let a0 = 1.0 + alpha;
+			let b = (1.0 - w.cos()) / 2.0 / a0;
+			[b as f32, (2.0 * b) as f32, b as f32, (-2.0 * w.cos() / a0) as f32, ((1.0 - alpha) / a0) as f32]
+		});
+		OutputFilter {
+			low_pass: one_pole_coefficient(low_pass_cutoff, sample_rate),
+			low_pass_state: 0.0,
+			high_pass: one_pole_coefficient(rc(1390.0, 22e-6), sample_rate),
+			high_pass_state: 0.0,
+			led,
+			led_state: [0.0; 4],
+		}
+	}
+
+	pub fn process(&mut self, input: f32) -> f32 {
+		self.low_pass_state += (input - self.low_pass_state) * self.low_pass;
+		let mut value = self.low_pass_state;
+		if let Some([b0, b1, b2, a1, a2]) = self.led {
+			let [x1, x2, y1, y2] = self.led_state;
+			let y = b0 * value + b1 * x1 + b2 * x2 - a1 * y1 - a2 * y2;
+			self.led_state = [value, x1, y, y1];
+			value = y;
+		}
+		self.high_pass_state += (value - self.high_pass_state) * self.high_pass;
+		value - self.high_pass_state
+	}
+}
+
+/// A single Paula channel playing a sample at a fixed period (no faster than
+/// the DMA can fetch), with the held sample values resynthesized as
+/// band-limited steps. The output is delayed by `BLEP_ZERO_CROSSINGS - 1` samples.
+#[derive(Clone)]
+pub struct PaulaVoice {
+	// Output samples between sample fetches
+	fetch_interval: f64,
+	until_fetch: f64,
+	next_index: usize,
+	level: f32,
+	buffer: [f32; BLEP_BUFFER_SIZE],
+	buffer_pos: usize,
+	filter: Option<OutputFilter>,
+}
+
+impl PaulaVoice {
+	pub fn new(period: u16, sample_rate: f32, amiga: Option<Amiga>) -> PaulaVoice {
+		PaulaVoice {
+			fetch_interval: period.max(124) as f64 * sample_rate as f64 / PAULA_CLOCK,
+			until_fetch: 1.0,
+			next_index: 0,
+			level: 0.0,
+			buffer: [0.0; BLEP_BUFFER_SIZE],
+			buffer_pos: 0,
+			filter: amiga.map(|amiga| OutputFilter::new(amiga, sample_rate)),
+		}
+	}
+
+	/// Index of the sample currently being played.
+	pub fn current_index(&self) -> usize {
+		self.next_index.saturating_sub(1)
+	}
+
+	/// Produce the next output sample, in the range -128 to 127,
+	/// fetching sample data as Paula would.
+	pub fn produce_sample(&mut self, mut fetch: impl FnMut(usize) -> i8) -> f32 {
+		let pos = self.buffer_pos;
+		let slot = |offset: isize| (pos as isize + offset).rem_euclid(BLEP_BUFFER_SIZE as isize) as usize;
+		self.buffer[slot(0)] += self.level;
+		while self.until_fetch <= 1.0 {
+			let value = fetch(self.next_index) as f32;
+			self.next_index += 1;
+			let delta = value - self.level;
+			if delta != 0.0 {
+				// Corrections for the outputs around the step, which lies between this and the next output
+				for offset in 1 - BLEP_ZERO_CROSSINGS as isize ..= 1 + BLEP_ZERO_CROSSINGS as isize {
+					self.buffer[slot(offset)] += delta * blep_residual(offset as f64 - self.until_fetch);
+				}
+				self.level = value;
+			}
+			self.until_fetch += self.fetch_interval;
+		}
+		self.until_fetch -= 1.0;
+
+		let out = slot(1 - BLEP_ZERO_CROSSINGS as isize);
+		let value = std::mem::take(&mut self.buffer[out]);
+		self.buffer_pos = slot(1);
+		match &mut self.filter {
+			Some(filter) => filter.process(value),
+			None => value,
+		}
+	}
+}
@@ -0,0 +1,59 @@
+use std::sync::Arc;
+
+use crate::engine::{CinterEngine, CinterInstrument, EngineMode, SynthVersion};
+use crate::module::{Instrument, Module, INSTRUMENT_COUNT};
+
+/// Cinter instrument whose stored sample data differs from its name.
+pub struct Stale {
+	pub number: usize,
+	pub name: String,
+	/// Offset in bytes of the first differing sample.
+	pub offset: usize,
+	/// Number of differing samples.
+	pub count: usize,
+	/// Largest difference between a stored and a generated sample.
+	pub max_difference: u8,
+}
+
+/// Sample data generated from the name of a Cinter instrument, at its current
+/// length, as saved by the GUI. None for raw and empty instruments.
+pub fn generate(engine: &Arc<CinterEngine>, instrument: &Instrument) -> Option<Vec<i8>> {
+	let name = instrument.name();
+	let (params, chord) = CinterEngine::chord_parameters_from_sample_filename(&name).ok()?;
+	let version = SynthVersion::from_sample_filename(&name);
+	let length = instrument.samples.len();
+	let mut generated = CinterInstrument::new(engine.clone(), version, &params, &chord, Some(length), None);
+	Some((0..length).map(|i| generated.get_sample_raw(i)).collect())
+}
+
+/// Cinter instruments in the module whose sample data differs from their names.
+pub fn stale_instruments(module: &Module) -> Vec<Stale> {
+	let engine = Arc::new(CinterEngine::with_mode(EngineMode::Exact));
+	(1..=INSTRUMENT_COUNT).filter_map(|number| {
+		let instrument = module.instrument(number);
+		let data = generate(&engine, instrument)?;
+		let differences: Vec<(usize, u8)> = data.iter().zip(&instrument.samples).enumerate()
+			.filter(|(_, (a, b))| a != b)
+			.map(|(i, (&a, &b))| (i, a.abs_diff(b)))
+			.collect();
+		Some(Stale {
+			number,
+			name: instrument.name(),
+			offset: differences.first()?.0,
+			count: differences.len(),
+			max_difference: differences.iter().map(|&(_, d)| d).max().unwrap(),
+		})
+	}).collect()
+}
+
+/// Regenerate the sample data of all Cinter instruments, keeping length,
+/// repeat, volume and finetune. Returns the instruments that changed.
+pub fn refresh(module: &mut Module) -> Vec<Stale> {
+	let stale = stale_instruments(module);
+	let engine = Arc::new(CinterEngine::with_mode(EngineMode::Exact));
+	for s in &stale {
+		let instrument = module.instrument_mut(s.number);
+		instrument.samples = generate(&engine, instrument).unwrap();
+	}
+	stale
+}
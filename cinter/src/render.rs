@@ -0,0 +1,144 @@
+use std::sync::Arc;
+
+use crate::engine::{CinterEngine, CinterInstrument, EngineMode};
+use crate::module::CHANNEL_COUNT;
+use crate::song::SongData;
+
+/// PAL color clock, which drives the Paula sample rate.
+pub const PAULA_CLOCK: f64 = 3546895.0;
+pub const VBLANK_RATE: u32 = 50;
+
+/// Sample memory as set up by CinterInit: raw instruments followed by generated ones.
+pub struct SampleMemory {
+	pub data: Vec<i8>,
+	/// Start of each instrument in bytes.
+	pub addresses: Vec<usize>,
+}
+
+impl SampleMemory {
+	pub fn new(song: &SongData, raw_data: &[u8]) -> anyhow::Result<SampleMemory> {
+		let engine = Arc::new(CinterEngine::with_mode(EngineMode::Exact));
+		let mut data = vec![];
+		let mut addresses = vec![];
+		let mut raw_pos = 0;
+		for record in &song.instruments {
+			addresses.push(data.len());
+			let size = record.length as usize * 2;
+			match &record.params {
+				Some(params) => {
+					let mut instrument = CinterInstrument::from_words(engine.clone(), params, size, None);
+					data.extend((0..size).map(|i| instrument.get_sample_raw(i)));
+				},
+				None => {
+					let samples = raw_data.get(raw_pos .. raw_pos + size)
+						.ok_or_else(|| anyhow::anyhow!("Raw instrument data too short"))?;
+					data.extend(samples.iter().map(|&s| s as i8));
+					raw_pos += size;
+				},
+			}
+		}
+		Ok(SampleMemory { data, addresses })
+	}
+}
+
+#[derive(Clone, Copy, Default)]
+struct PaulaChannel {
+	// Registers
+	location: usize,
+	length: u16,
+	period: u16,
+	volume: u16,
+
+	// DMA state
+	playing: bool,
+	pointer: usize,
+	remaining: usize,
+	phase: f64,
+}
+
+impl PaulaChannel {
+	fn start_dma(&mut self) {
+		self.playing = true;
+		self.pointer = self.location;
+		self.remaining = length_in_bytes(self.length);
+		self.phase = 0.0;
+	}
+
+	fn output(&self, memory: &[i8]) -> i32 {
+		if self.playing {
+			let sample = memory.get(self.pointer).copied().unwrap_or(0);
+			sample as i32 * self.volume.min(64) as i32
+		} else {
+			0
+		}
+	}
+
+	fn advance(&mut self, sample_rate: f64) {
+		if !self.playing {
+			return;
+		}
+		// Paula cannot fetch samples faster than this.
+		self.phase += PAULA_CLOCK / (self.period.max(124) as f64 * sample_rate);
+		while self.phase >= 1.0 {
+			self.phase -= 1.0;
+			self.pointer += 1;
+			self.remaining -= 1;
+			if self.remaining == 0 {
+				// Reload from the registers when the block is done.
+				self.pointer = self.location;
+				self.remaining = length_in_bytes(self.length);
+			}
+		}
+	}
+}
+
+fn length_in_bytes(length: u16) -> usize {
+	if length == 0 { 0x20000 } else { length as usize * 2 }
+}
+
+/// Render the song through a model of the four Paula channels, with channels
+/// 0 and 3 to the left and 1 and 2 to the right. No filtering is applied,
+/// matching the player, which turns off the LED filter.
+pub fn render(song: &SongData, raw_data: &[u8], vblanks: usize, sample_rate: u32) -> anyhow::Result<Vec<[i16; 2]>> {
+	let memory = SampleMemory::new(song, raw_data)?;
+	let frames = song.frames(vblanks)?;
+
+	let mut channels = [PaulaChannel::default(); CHANNEL_COUNT];
+	// Repeat location and length, as kept in c_MusicState
+	let mut repeats = [(0, 0); CHANNEL_COUNT];
+	let mut output = vec![];
+	for (vblank, frame) in frames.iter().enumerate() {
+		for ((channel, repeat), state) in channels.iter_mut().zip(&mut repeats).zip(frame) {
+			if let Some(trigger) = state.trigger {
+				let record = &song.instruments[trigger.instrument];
+				let address = memory.addresses[trigger.instrument];
+				*repeat = if record.replen != 0 {
+					(address + record.length.saturating_sub(record.replen) as usize * 2, record.replen)
+				} else {
+					(address, 1)
+				};
+				channel.location = address + trigger.offset as usize * 2;
+				channel.length = record.length.wrapping_sub(trigger.offset);
+				channel.start_dma();
+			} else {
+				(channel.location, channel.length) = *repeat;
+			}
+			channel.period = state.period;
+			channel.volume = state.volume;
+		}
+
+		let end = (vblank + 1) * sample_rate as usize / VBLANK_RATE as usize;
+		while output.len() < end {
+			let mut mix = [0i32; CHANNEL_COUNT];
+			for (m, channel) in mix.iter_mut().zip(&mut channels) {
+				*m = channel.output(&memory.data);
+				channel.advance(sample_rate as f64);
+			}
+			let left = (mix[0] + mix[3]) * 2;
+			let right = (mix[1] + mix[2]) * 2;
+			output.push([left.clamp(-32768, 32767) as i16, right.clamp(-32768, 32767) as i16]);
+		}
+	}
+
+	Ok(output)
+}
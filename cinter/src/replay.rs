@@ -0,0 +1,704 @@
+use crate::convert::schedule_ticks;
+use crate::module::{Module, TrackRow, CHANNEL_COUNT, ROW_COUNT};
+use crate::song::{ChannelFrame, Trigger};
+
+// Protracker 2.3d period table, 37 entries per finetune (the last one always 0).
+const PT_PERIODS: [u16; 16 * 37] = [
+	// Finetune 0
+	856, 808, 762, 720, 678, 640, 604, 570, 538, 508, 480, 453,
+	428, 404, 381, 360, 339, 320, 302, 285, 269, 254, 240, 226,
+	214, 202, 190, 180, 170, 160, 151, 143, 135, 127, 120, 113, 0,
+	// Finetune 1
+	850, 802, 757, 715, 674, 637, 601, 567, 535, 505, 477, 450,
+	425, 401, 379, 357, 337, 318, 300, 284, 268, 253, 239, 225,
+	213, 201, 189, 179, 169, 159, 150, 142, 134, 126, 119, 113, 0,
+	// Finetune 2
+	844, 796, 752, 709, 670, 632, 597, 563, 532, 502, 474, 447,
+	422, 398, 376, 355, 335, 316, 298, 282, 266, 251, 237, 224,
+	211, 199, 188, 177, 167, 158, 149, 141, 133, 125, 118, 112, 0,
+	// Finetune 3
+	838, 791, 746, 704, 665, 628, 592, 559, 528, 498, 470, 444,
+	419, 395, 373, 352, 332, 314, 296, 280, 264, 249, 235, 222,
+	209, 198, 187, 176, 166, 157, 148, 140, 132, 125, 118, 111, 0,
+	// Finetune 4
+	832, 785, 741, 699, 660, 623, 588, 555, 524, 495, 467, 441,
+	416, 392, 370, 350, 330, 312, 294, 278, 262, 247, 233, 220,
+	208, 196, 185, 175, 165, 156, 147, 139, 131, 124, 117, 110, 0,
+	// Finetune 5
+	826, 779, 736, 694, 655, 619, 584, 551, 520, 491, 463, 437,
+	413, 390, 368, 347, 328, 309, 292, 276, 260, 245, 232, 219,
+	206, 195, 184, 174, 164, 155, 146, 138, 130, 123, 116, 109, 0,
+	// Finetune 6
+	820, 774, 730, 689, 651, 614, 580, 547, 516, 487, 460, 434,
+	410, 387, 365, 345, 325, 307, 290, 274, 258, 244, 230, 217,
+	205, 193, 183, 172, 163, 154, 145, 137, 129, 122, 115, 109, 0,
+	// Finetune 7
+	814, 768, 725, 684, 646, 610, 575, 543, 513, 484, 457, 431,
+	407, 384, 363, 342, 323, 305, 288, 272, 256, 242, 228, 216,
+	204, 192, 181, 171, 161, 152, 144, 136, 128, 121, 114, 108, 0,
+	// Finetune -8
+	907, 856, 808, 762, 720, 678, 640, 604, 570, 538, 508, 480,
+	453, 428, 404, 381, 360, 339, 320, 302, 285, 269, 254, 240,
+	226, 214, 202, 190, 180, 170, 160, 151, 143, 135, 127, 120, 0,
+	// Finetune -7
+	900, 850, 802, 757, 715, 675, 636, 601, 567, 535, 505, 477,
+	450, 425, 401, 379, 357, 337, 318, 300, 284, 268, 253, 238,
+	225, 212, 200, 189, 179, 169, 159, 150, 142, 134, 126, 119, 0,
+	// Finetune -6
+	894, 844, 796, 752, 709, 670, 632, 597, 563, 532, 502, 474,
+	447, 422, 398, 376, 355, 335, 316, 298, 282, 266, 251, 237,
+	223, 211, 199, 188, 177, 167, 158, 149, 141, 133, 125, 118, 0,
+	// Finetune -5
+	887, 838, 791, 746, 704, 665, 628, 592, 559, 528, 498, 470,
+	444, 419, 395, 373, 352, 332, 314, 296, 280, 264, 249, 235,
+	222, 209, 198, 187, 176, 166, 157, 148, 140, 132, 125, 118, 0,
+	// Finetune -4
+	881, 832, 785, 741, 699, 660, 623, 588, 555, 524, 494, 467,
+	441, 416, 392, 370, 350, 330, 312, 294, 278, 262, 247, 233,
+	220, 208, 196, 185, 175, 165, 156, 147, 139, 131, 123, 117, 0,
+	// Finetune -3
+	875, 826, 779, 736, 694, 655, 619, 584, 551, 520, 491, 463,
+	437, 413, 390, 368, 347, 328, 309, 292, 276, 260, 245, 232,
+	219, 206, 195, 184, 174, 164, 155, 146, 138, 130, 123, 116, 0,
+	// Finetune -2
+	868, 820, 774, 730, 689, 651, 614, 580, 547, 516, 487, 460,
+	434, 410, 387, 365, 345, 325, 307, 290, 274, 258, 244, 230,
+	217, 205, 193, 183, 172, 163, 154, 145, 137, 129, 122, 115, 0,
+	// Finetune -1
+	862, 814, 768, 725, 684, 646, 610, 575, 543, 513, 484, 457,
+	431, 407, 384, 363, 342, 323, 305, 288, 272, 256, 242, 228,
+	216, 203, 192, 181, 171, 161, 152, 144, 136, 128, 121, 114, 0,
+];
+
+const VIBRATO_TABLE: [u8; 32] = [
+	0, 24, 49, 74, 97, 120, 141, 161, 180, 197, 212, 224, 235, 244, 250, 253,
+	255, 253, 250, 244, 235, 224, 212, 197, 180, 161, 141, 120, 97, 74, 49, 24,
+];
+
+// Table lookups run past the end of a finetune into the next, as in Protracker.
+fn pt_period(finetune: u8, index: usize) -> u16 {
+	PT_PERIODS.get((finetune & 15) as usize * 37 + index).copied().unwrap_or(0)
+}
+
+// Index of the first period in the table not above the given period.
+fn pt_period_index(finetune: u8, period: u16) -> Option<usize> {
+	(0..37).find(|&i| period >= pt_period(finetune, i))
+}
+
+/// Channel states of a vblank when playing a module in Protracker 2.3d.
+#[derive(Clone, Copy, Debug)]
+pub struct ReplayFrame {
+	pub position: usize,
+	pub pattern: usize,
+	pub row: usize,
+	/// Triggers refer to module instrument numbers.
+	pub channels: [ChannelFrame; CHANNEL_COUNT],
+}
+
+// Channel variables of the Protracker replay routine
+#[derive(Clone, Copy, Default)]
+struct Voice {
+	row: TrackRow,
+	instrument: usize,
+	/// Sample start offset and length in words.
+	start: u32,
+	length: u16,
+	finetune: u8,
+	volume: u8,
+	period: u16,
+	wanted_period: u16,
+	tone_port_up: bool,
+	tone_port_speed: u8,
+	vibrato_cmd: u8,
+	vibrato_pos: u8,
+	tremolo_cmd: u8,
+	tremolo_pos: u8,
+	wave_control: u8,
+	gliss_funk: u8,
+	sample_offset: u8,
+	loop_row: u8,
+	loop_count: u8,
+
+	/// Audio registers
+	frame: ChannelFrame,
+}
+
+/// Tick by tick model of mt_music in Protracker 2.3d.
+pub struct Replayer<'m> {
+	module: &'m Module,
+	voices: [Voice; CHANNEL_COUNT],
+	speed: u8,
+	/// CIA tempo, which sets the length of the ticks.
+	tempo: u8,
+	counter: u8,
+	song_pos: u8,
+	pattern_pos: usize,
+	pbreak_pos: u8,
+	pbreak_flag: bool,
+	pos_jump_flag: bool,
+	patt_del_time: u8,
+	patt_del_time2: u8,
+	low_mask: u8,
+	stopped: bool,
+
+	// Row read most recently
+	position: usize,
+	pattern: usize,
+	row: usize,
+}
+
+/// Channel states for each vblank, until the given number of vblanks or until F00 stops the song.
+/// Each tick is played in a vblank of its own, whatever the CIA tempo.
+pub fn replay(module: &Module, vblanks: usize) -> Vec<ReplayFrame> {
+	let mut replayer = Replayer::new(module);
+	let mut frames = vec![];
+	while frames.len() < vblanks && !replayer.stopped() {
+		frames.push(replayer.tick());
+	}
+	frames
+}
+
+/// Channel states for each vblank with the ticks at the CIA tempo, spread over
+/// the vblanks as by the converter with the CIA tempo option. Ticks without a
+/// vblank of their own are skipped, except that triggers are played in the next
+/// vblank along with the period and volume of their tick.
+pub fn replay_cia_tempo(module: &Module, vblanks: usize) -> Vec<ReplayFrame> {
+	let mut replayer = Replayer::new(module);
+	let mut ticks = vec![];
+	let mut tempos = vec![];
+	let mut time = 0.0;
+	while time < vblanks as f64 + 1.0 && !replayer.stopped() {
+		let tempo = replayer.tempo;
+		tempos.push(tempo);
+		ticks.push(replayer.tick());
+		time += 125.0 / tempo as f64;
+	}
+	let (starts, _) = schedule_ticks(&tempos);
+	let mut frames = vec![];
+	let mut pending = [None; CHANNEL_COUNT];
+	for (tick, frame) in ticks.iter().enumerate() {
+		for (pending, channel) in pending.iter_mut().zip(&frame.channels) {
+			if channel.trigger.is_some() {
+				*pending = Some(*channel);
+			}
+		}
+		for vblank in starts[tick]..starts[tick + 1] {
+			let mut frame = *frame;
+			for (channel, pending) in frame.channels.iter_mut().zip(&mut pending) {
+				if vblank == starts[tick] {
+					*channel = pending.take().unwrap_or(*channel);
+				} else {
+					channel.trigger = None;
+				}
+			}
+			frames.push(frame);
+		}
+	}
+	frames.truncate(vblanks);
+	frames
+}
+
+impl<'m> Replayer<'m> {
+	pub fn new(module: &'m Module) -> Self {
+		Replayer {
+			module,
+			voices: [Voice::default(); CHANNEL_COUNT],
+			speed: 6,
+			tempo: 125,
+			// First tick reads the first row.
+			counter: 5,
+			song_pos: 0,
+			pattern_pos: 0,
+			pbreak_pos: 0,
+			pbreak_flag: false,
+			pos_jump_flag: false,
+			patt_del_time: 0,
+			patt_del_time2: 0,
+			low_mask: 0xFF,
+			stopped: false,
+
+			position: 0,
+			pattern: 0,
+			row: 0,
+		}
+	}
+
+	pub fn stopped(&self) -> bool {
+		self.stopped
+	}
+
+	pub fn tick(&mut self) -> ReplayFrame {
+		for voice in &mut self.voices {
+			voice.frame.trigger = None;
+		}
+
+		self.counter += 1;
+		if self.counter >= self.speed {
+			self.counter = 0;
+			if self.patt_del_time2 == 0 {
+				self.get_new_note();
+			} else {
+				self.no_new_all_channels();
+			}
+
+			self.pattern_pos += 1;
+			if self.patt_del_time != 0 {
+				self.patt_del_time2 = self.patt_del_time;
+				self.patt_del_time = 0;
+			}
+			if self.patt_del_time2 != 0 {
+				self.patt_del_time2 -= 1;
+				if self.patt_del_time2 != 0 {
+					self.pattern_pos -= 1;
+				}
+			}
+			if self.pbreak_flag {
+				self.pbreak_flag = false;
+				self.pattern_pos = self.pbreak_pos as usize;
+				self.pbreak_pos = 0;
+			}
+			if self.pattern_pos >= ROW_COUNT {
+				self.next_position();
+			}
+		} else {
+			self.no_new_all_channels();
+		}
+		if self.pos_jump_flag {
+			self.next_position();
+		}
+
+		ReplayFrame {
+			position: self.position,
+			pattern: self.pattern,
+			row: self.row,
+			channels: self.voices.map(|voice| voice.frame),
+		}
+	}
+
+	fn next_position(&mut self) {
+		self.pattern_pos = self.pbreak_pos as usize;
+		self.pbreak_pos = 0;
+		self.pos_jump_flag = false;
+		self.song_pos = self.song_pos.wrapping_add(1) & 0x7F;
+		if self.song_pos >= self.module.song_length {
+			self.song_pos = 0;
+		}
+	}
+
+	fn get_new_note(&mut self) {
+		self.position = self.song_pos as usize;
+		self.pattern = self.module.positions[self.position] as usize;
+		self.row = self.pattern_pos;
+		for c in 0..CHANNEL_COUNT {
+			let row = self.module.patterns[self.pattern].rows[self.row][c];
+			self.play_voice(c, row);
+		}
+	}
+
+	fn no_new_all_channels(&mut self) {
+		for c in 0..CHANNEL_COUNT {
+			self.check_efx(c);
+		}
+	}
+
+	fn play_voice(&mut self, c: usize, row: TrackRow) {
+		let v = &mut self.voices[c];
+		if v.row == TrackRow::default() {
+			v.frame.period = v.period;
+		}
+		v.row = row;
+		let number = row.instrument as usize;
+		if number != 0 {
+			let inst = self.module.instruments.get(number - 1).cloned().unwrap_or_default();
+			v.instrument = number;
+			v.start = 0;
+			v.length = inst.length() as u16;
+			v.finetune = inst.finetune & 15;
+			v.volume = inst.volume;
+			if inst.repeat_offset != 0 {
+				v.length = inst.repeat_offset.wrapping_add(inst.repeat_length);
+			}
+			v.frame.volume = v.volume as u16;
+		}
+
+		if row.period == 0 {
+			self.check_more_efx(c);
+			return;
+		}
+		match (row.command, row.argument >> 4) {
+			(0xE, 0x5) => self.set_fine_tune(c),
+			(0x3 | 0x5, _) => {
+				self.set_tone_porta(c);
+				self.check_more_efx(c);
+				return;
+			},
+			(0x9, _) => self.check_more_efx(c),
+			_ => {},
+		}
+
+		// mt_SetPeriod
+		let v = &mut self.voices[c];
+		let index = pt_period_index(0, row.period).unwrap_or(37);
+		v.period = pt_period(v.finetune, index);
+		if (row.command, row.argument >> 4) != (0xE, 0xD) {
+			if v.wave_control & 0x04 == 0 {
+				v.vibrato_pos = 0;
+			}
+			if v.wave_control & 0x40 == 0 {
+				v.tremolo_pos = 0;
+			}
+			v.frame.trigger = Some(Trigger { instrument: v.instrument, offset: v.start as u16 });
+			v.frame.period = v.period;
+		}
+		self.check_more_efx(c);
+	}
+
+	// Effects on the first tick of a row
+	fn check_more_efx(&mut self, c: usize) {
+		match self.voices[c].row.command {
+			0x9 => self.sample_offset(c),
+			0xB => self.position_jump(c),
+			0xD => self.pattern_break(c),
+			0xE => self.e_commands(c),
+			0xF => self.set_speed(c),
+			0xC => self.volume_change(c),
+			_ => self.per_nop(c),
+		}
+	}
+
+	// Effects on the remaining ticks, and on the first tick of delayed rows
+	fn check_efx(&mut self, c: usize) {
+		let row = self.voices[c].row;
+		if row.command == 0 && row.argument == 0 {
+			self.per_nop(c);
+			return;
+		}
+		match row.command {
+			0x0 => self.arpeggio(c),
+			0x1 => self.porta_up(c),
+			0x2 => self.porta_down(c),
+			0x3 => self.tone_portamento(c),
+			0x4 => self.vibrato(c),
+			0x5 => {
+				self.tone_port_no_change(c);
+				self.volume_slide(c);
+			},
+			0x6 => {
+				self.vibrato2(c);
+				self.volume_slide(c);
+			},
+			0xE => self.e_commands(c),
+			cmd => {
+				self.per_nop(c);
+				match cmd {
+					0x7 => self.tremolo(c),
+					0xA => self.volume_slide(c),
+					_ => {},
+				}
+			},
+		}
+	}
+
+	fn e_commands(&mut self, c: usize) {
+		let arg = self.voices[c].row.argument;
+		let x = arg & 0x0F;
+		let first_tick = self.counter == 0;
+		match arg >> 4 {
+			0x1 if first_tick => {
+				self.low_mask = 0x0F;
+				self.porta_up(c);
+			},
+			0x2 if first_tick => {
+				self.low_mask = 0x0F;
+				self.porta_down(c);
+			},
+			0x3 => {
+				let v = &mut self.voices[c];
+				v.gliss_funk = v.gliss_funk & 0xF0 | x;
+			},
+			0x4 => {
+				let v = &mut self.voices[c];
+				v.wave_control = v.wave_control & 0xF0 | x;
+			},
+			0x5 => self.set_fine_tune(c),
+			0x6 if first_tick => self.jump_loop(c),
+			0x7 => {
+				let v = &mut self.voices[c];
+				v.wave_control = v.wave_control & 0x0F | x << 4;
+			},
+			0x9 => self.retrig_note(c),
+			0xA if first_tick => self.vol_slide_up(c, x),
+			0xB if first_tick => self.vol_slide_down(c, x),
+			0xC if x == self.counter => {
+				let v = &mut self.voices[c];
+				v.volume = 0;
+				v.frame.volume = 0;
+			},
+			0xD if x == self.counter && self.voices[c].row.period != 0 => self.do_retrig(c),
+			0xE if first_tick && self.patt_del_time2 == 0 => self.patt_del_time = x + 1,
+			// E0x (filter) and EFx (funk repeat) have no effect on channel state.
+			_ => {},
+		}
+	}
+
+	fn per_nop(&mut self, c: usize) {
+		let v = &mut self.voices[c];
+		v.frame.period = v.period;
+	}
+
+	fn arpeggio(&mut self, c: usize) {
+		let v = &mut self.voices[c];
+		let step = match self.counter % 3 {
+			0 => {
+				v.frame.period = v.period;
+				return;
+			},
+			1 => v.row.argument >> 4,
+			_ => v.row.argument & 0x0F,
+		};
+		if let Some(index) = pt_period_index(v.finetune, v.period) {
+			v.frame.period = pt_period(v.finetune, index + step as usize);
+		}
+	}
+
+	fn porta_up(&mut self, c: usize) {
+		let v = &mut self.voices[c];
+		let speed = v.row.argument & self.low_mask;
+		self.low_mask = 0xFF;
+		v.period = v.period.wrapping_sub(speed as u16);
+		if v.period & 0x0FFF < 113 {
+			v.period = v.period & 0xF000 | 113;
+		}
+		v.frame.period = v.period & 0x0FFF;
+	}
+
+	fn porta_down(&mut self, c: usize) {
+		let v = &mut self.voices[c];
+		let speed = v.row.argument & self.low_mask;
+		self.low_mask = 0xFF;
+		v.period = v.period.wrapping_add(speed as u16);
+		if v.period & 0x0FFF >= 856 {
+			v.period = v.period & 0xF000 | 856;
+		}
+		v.frame.period = v.period & 0x0FFF;
+	}
+
+	fn set_tone_porta(&mut self, c: usize) {
+		let v = &mut self.voices[c];
+		let mut index = pt_period_index(v.finetune, v.row.period).unwrap_or(35);
+		if v.finetune & 8 != 0 && index != 0 {
+			index -= 1;
+		}
+		v.wanted_period = pt_period(v.finetune, index);
+		v.tone_port_up = false;
+		if v.wanted_period == v.period {
+			v.wanted_period = 0;
+		} else if (v.wanted_period as i16) < (v.period as i16) {
+			v.tone_port_up = true;
+		}
+	}
+
+	fn tone_portamento(&mut self, c: usize) {
+		let v = &mut self.voices[c];
+		if v.row.argument != 0 {
+			v.tone_port_speed = v.row.argument;
+			v.row.argument = 0;
+		}
+		self.tone_port_no_change(c);
+	}
+
+	fn tone_port_no_change(&mut self, c: usize) {
+		let v = &mut self.voices[c];
+		if v.wanted_period == 0 {
+			return;
+		}
+		let speed = v.tone_port_speed as u16;
+		let reached = if v.tone_port_up {
+			v.period = v.period.wrapping_sub(speed);
+			v.wanted_period as i16 >= v.period as i16
+		} else {
+			v.period = v.period.wrapping_add(speed);
+			v.wanted_period as i16 <= v.period as i16
+		};
+		if reached {
+			v.period = v.wanted_period;
+			v.wanted_period = 0;
+		}
+		let mut period = v.period;
+		if v.gliss_funk & 0x0F != 0 {
+			let index = pt_period_index(v.finetune, period).unwrap_or(35);
+			period = pt_period(v.finetune, index);
+		}
+		v.frame.period = period;
+	}
+
+	fn vibrato(&mut self, c: usize) {
+		let v = &mut self.voices[c];
+		let arg = v.row.argument;
+		if arg != 0 {
+			if arg & 0x0F != 0 {
+				v.vibrato_cmd = v.vibrato_cmd & 0xF0 | arg & 0x0F;
+			}
+			if arg & 0xF0 != 0 {
+				v.vibrato_cmd = v.vibrato_cmd & 0x0F | arg & 0xF0;
+			}
+		}
+		self.vibrato2(c);
+	}
+
+	fn vibrato2(&mut self, c: usize) {
+		let v = &mut self.voices[c];
+		let amplitude = (wave_value(v.wave_control, v.vibrato_pos, v.vibrato_pos) * (v.vibrato_cmd & 15) as u16) >> 7;
+		v.frame.period = if (v.vibrato_pos as i8) < 0 {
+			v.period.wrapping_sub(amplitude)
+		} else {
+			v.period.wrapping_add(amplitude)
+		};
+		v.vibrato_pos = v.vibrato_pos.wrapping_add(v.vibrato_cmd >> 2 & 0x3C);
+	}
+
+	fn tremolo(&mut self, c: usize) {
+		let v = &mut self.voices[c];
+		let arg = v.row.argument;
+		if arg != 0 {
+			if arg & 0x0F != 0 {
+				v.tremolo_cmd = v.tremolo_cmd & 0xF0 | arg & 0x0F;
+			}
+			if arg & 0xF0 != 0 {
+				v.tremolo_cmd = v.tremolo_cmd & 0x0F | arg & 0xF0;
+			}
+		}
+		// The ramp waveform looks at the vibrato position, as in Protracker.
+		let amplitude = (wave_value(v.wave_control >> 4, v.tremolo_pos, v.vibrato_pos) * (v.tremolo_cmd & 15) as u16) >> 6;
+		let volume = if (v.tremolo_pos as i8) < 0 {
+			v.volume as i16 - amplitude as i16
+		} else {
+			v.volume as i16 + amplitude as i16
+		};
+		v.frame.volume = volume.clamp(0, 64) as u16;
+		v.tremolo_pos = v.tremolo_pos.wrapping_add(v.tremolo_cmd >> 2 & 0x3C);
+	}
+
+	fn volume_slide(&mut self, c: usize) {
+		let arg = self.voices[c].row.argument;
+		if arg >> 4 != 0 {
+			self.vol_slide_up(c, arg >> 4);
+		} else {
+			self.vol_slide_down(c, arg & 0x0F);
+		}
+	}
+
+	fn vol_slide_up(&mut self, c: usize, amount: u8) {
+		let v = &mut self.voices[c];
+		v.volume = v.volume.wrapping_add(amount);
+		if v.volume.wrapping_sub(0x40) as i8 >= 0 {
+			v.volume = 0x40;
+		}
+		v.frame.volume = v.volume as u16;
+	}
+
+	fn vol_slide_down(&mut self, c: usize, amount: u8) {
+		let v = &mut self.voices[c];
+		v.volume = v.volume.wrapping_sub(amount);
+		if (v.volume as i8) < 0 {
+			v.volume = 0;
+		}
+		v.frame.volume = v.volume as u16;
+	}
+
+	fn volume_change(&mut self, c: usize) {
+		let v = &mut self.voices[c];
+		v.volume = v.row.argument.min(0x40);
+		v.frame.volume = v.volume as u16;
+	}
+
+	fn sample_offset(&mut self, c: usize) {
+		let v = &mut self.voices[c];
+		if v.row.argument != 0 {
+			v.sample_offset = v.row.argument;
+		}
+		let offset = (v.sample_offset as u16) << 7;
+		if offset as i16 >= v.length as i16 {
+			v.length = 1;
+		} else {
+			v.length -= offset;
+			v.start += offset as u32;
+		}
+	}
+
+	fn position_jump(&mut self, c: usize) {
+		self.song_pos = self.voices[c].row.argument.wrapping_sub(1);
+		self.pbreak_pos = 0;
+		self.pos_jump_flag = true;
+	}
+
+	fn pattern_break(&mut self, c: usize) {
+		let arg = self.voices[c].row.argument;
+		let row = (arg >> 4) * 10 + (arg & 0x0F);
+		self.pbreak_pos = if row > 63 { 0 } else { row };
+		self.pos_jump_flag = true;
+	}
+
+	fn set_speed(&mut self, c: usize) {
+		match self.voices[c].row.argument {
+			0 => self.stopped = true,
+			tempo if tempo >= 32 => self.tempo = tempo,
+			speed => {
+				self.counter = 0;
+				self.speed = speed;
+			},
+		}
+	}
+
+	fn set_fine_tune(&mut self, c: usize) {
+		let v = &mut self.voices[c];
+		v.finetune = v.row.argument & 0x0F;
+	}
+
+	fn jump_loop(&mut self, c: usize) {
+		let v = &mut self.voices[c];
+		let count = v.row.argument & 0x0F;
+		if count == 0 {
+			v.loop_row = self.pattern_pos as u8;
+			return;
+		}
+		if v.loop_count == 0 {
+			v.loop_count = count;
+		} else {
+			v.loop_count -= 1;
+			if v.loop_count == 0 {
+				return;
+			}
+		}
+		self.pbreak_pos = v.loop_row;
+		self.pbreak_flag = true;
+	}
+
+	fn retrig_note(&mut self, c: usize) {
+		let v = &self.voices[c];
+		let interval = v.row.argument & 0x0F;
+		if interval == 0 || (self.counter == 0 && v.row.period != 0) {
+			return;
+		}
+		if self.counter.is_multiple_of(interval) {
+			self.do_retrig(c);
+		}
+	}
+
+	fn do_retrig(&mut self, c: usize) {
+		let v = &mut self.voices[c];
+		v.frame.trigger = Some(Trigger { instrument: v.instrument, offset: v.start as u16 });
+		v.frame.period = v.period;
+	}
+}
+
+// Vibrato and tremolo waveforms. The ramp direction follows ramp_pos.
+pub(crate) fn wave_value(control: u8, pos: u8, ramp_pos: u8) -> u16 {
+	let index = (pos >> 2 & 0x1F) as usize;
+	match control & 3 {
+		0 => VIBRATO_TABLE[index] as u16,
+		1 if (ramp_pos as i8) < 0 => 255 - (index as u16) * 8,
+		1 => (index as u16) * 8,
+		_ => 255,
+	}
+}
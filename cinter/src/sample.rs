@@ -0,0 +1,58 @@
+/// Sample rate written to sample files.
+pub const SAMPLE_RATE: u32 = 16726;
+
+/// An instrument as stored in a sample file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SampleFile {
+	/// Sample name, which holds the parameters for Cinter instruments.
+	pub name: Option<String>,
+	pub data: Vec<i8>,
+	/// Length of the repeat at the end of the sample, or 0 for no repeat.
+	pub repeat_length: usize,
+}
+
+impl SampleFile {
+	/// Start of the repeat, if any.
+	pub fn repeat_start(&self) -> Option<usize> {
+		(self.repeat_length > 0).then(|| self.data.len() - self.repeat_length)
+	}
+}
+
+/// Split the body of a RIFF or IFF container into chunks, skipping pad bytes.
+pub(crate) fn read_chunks(data: &[u8], big_endian: bool) -> anyhow::Result<Vec<([u8; 4], &[u8])>> {
+	let mut chunks = vec![];
+	let mut index = 0;
+	while index + 8 <= data.len() {
+		let id: [u8; 4] = data[index .. index + 4].try_into()?;
+		let size_bytes = data[index + 4 .. index + 8].try_into()?;
+		let size = if big_endian { u32::from_be_bytes(size_bytes) } else { u32::from_le_bytes(size_bytes) } as usize;
+		let start = index + 8;
+		if size > data.len() - start {
+			return Err(anyhow::anyhow!("{} chunk truncated", String::from_utf8_lossy(&id)));
+		}
+		chunks.push((id, &data[start .. start + size]));
+		index = start + size + (size & 1);
+	}
+	Ok(chunks)
+}
+
+pub(crate) fn find_chunk<'d>(chunks: &[([u8; 4], &'d [u8])], id: &[u8; 4]) -> Option<&'d [u8]> {
+	chunks.iter().find(|(chunk_id, _)| chunk_id == id).map(|&(_, body)| body)
+}
+
+/// Append a chunk with a pad byte if its size is odd.
+pub(crate) fn write_chunk(out: &mut Vec<u8>, id: &[u8; 4], big_endian: bool, body: &[u8]) {
+	out.extend_from_slice(id);
+	let size = body.len() as u32;
+	out.extend_from_slice(&if big_endian { size.to_be_bytes() } else { size.to_le_bytes() });
+	out.extend_from_slice(body);
+	if body.len() & 1 != 0 {
+		out.push(0);
+	}
+}
+
+// Text up to the first NUL, as written in name chunks.
+pub(crate) fn read_text(body: &[u8]) -> String {
+	let end = body.iter().position(|&b| b == 0).unwrap_or(body.len());
+	String::from_utf8_lossy(&body[..end]).trim().to_string()
+}
@@ -0,0 +1,247 @@
+use crate::convert::PERIOD_TABLE;
+use crate::engine::WordParameters;
+use crate::module::CHANNEL_COUNT;
+
+/// Instrument record as read by CinterMakeInstruments in Cinter4.S.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InstrumentRecord {
+	/// Sample length in words.
+	pub length: u16,
+	/// Repeat length in words, or 0 for no repeat.
+	pub replen: u16,
+	/// Synth parameters, or None for a raw instrument.
+	pub params: Option<WordParameters>,
+}
+
+impl InstrumentRecord {
+	/// Append the record on the form read by CinterMakeInstruments.
+	pub fn write(&self, out: &mut Vec<u8>) {
+		let mut words = vec![self.length, self.replen];
+		if let Some(p) = &self.params {
+			words.extend([p.mpitch, p.mod_, p.bpitch, p.attack, p.dist, p.decay, p.mpitchdecay, p.moddecay, p.bpitchdecay]);
+		}
+		for word in words {
+			out.extend_from_slice(&word.to_be_bytes());
+		}
+	}
+}
+
+/// Instruments the player has room for (c_Instruments in Cinter4.S).
+pub const MAX_INSTRUMENTS: usize = 32;
+
+/// Encode an instrument list as at the start of the song data, with the
+/// raw instruments (which must come first) preceded by their negated count.
+pub fn write_instruments(instruments: &[InstrumentRecord]) -> anyhow::Result<Vec<u8>> {
+	let raw_count = instruments.iter().take_while(|inst| inst.params.is_none()).count();
+	if instruments[raw_count..].iter().any(|inst| inst.params.is_none()) {
+		return Err(anyhow::anyhow!("Raw instruments must come before generated instruments"));
+	}
+	// The player always makes at least one generated instrument.
+	if raw_count == instruments.len() {
+		return Err(anyhow::anyhow!("At least one generated instrument is needed"));
+	}
+	if instruments.len() > MAX_INSTRUMENTS {
+		return Err(anyhow::anyhow!("Too many instruments ({}, the player has room for {})", instruments.len(), MAX_INSTRUMENTS));
+	}
+	if let Some(inst) = instruments.iter().find(|inst| inst.length == 0 || inst.replen > inst.length) {
+		return Err(anyhow::anyhow!("Invalid length {} and repeat length {}", inst.length, inst.replen));
+	}
+
+	let mut data = vec![];
+	if raw_count > 0 {
+		data.extend_from_slice(&(-(raw_count as i16)).to_be_bytes());
+		instruments[..raw_count].iter().for_each(|inst| inst.write(&mut data));
+	}
+	data.extend_from_slice(&((instruments.len() - raw_count - 1) as i16).to_be_bytes());
+	instruments[raw_count..].iter().for_each(|inst| inst.write(&mut data));
+	Ok(data)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NoteRange {
+	pub note_min: u8,
+	pub count: u8,
+	/// Sample offset in words. A range with offset 0 starts the next instrument.
+	pub offset: u16,
+}
+
+/// Song data file as written by the converter.
+#[derive(Clone)]
+pub struct SongData {
+	/// Raw instruments followed by generated instruments.
+	pub instruments: Vec<InstrumentRecord>,
+	pub note_ranges: Vec<NoteRange>,
+	/// Vblank to continue from when the end is reached.
+	pub restart: usize,
+	/// One word per vblank for each channel.
+	pub tracks: [Vec<u16>; CHANNEL_COUNT],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Trigger {
+	/// Index into the instrument list of the song data,
+	/// or the instrument number when replaying a module.
+	pub instrument: usize,
+	/// Sample offset in words.
+	pub offset: u16,
+}
+
+/// Channel state after a vblank, as written to the audio registers by CinterPlay2.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChannelFrame {
+	pub trigger: Option<Trigger>,
+	pub period: u16,
+	pub volume: u16,
+}
+
+struct SongReader<'d> {
+	data: &'d [u8],
+	pos: usize,
+}
+
+impl<'d> SongReader<'d> {
+	fn read_u16(&mut self, what: &str) -> anyhow::Result<u16> {
+		let bytes = self.data.get(self.pos .. self.pos + 2)
+			.ok_or_else(|| anyhow::anyhow!("Song data truncated in {}", what))?;
+		self.pos += 2;
+		Ok(u16::from_be_bytes(bytes.try_into()?))
+	}
+}
+
+impl SongData {
+	pub fn read(data: &[u8]) -> anyhow::Result<SongData> {
+		let r = &mut SongReader { data, pos: 0 };
+		let what = "instrument records";
+		let mut instruments = vec![];
+		let mut count = r.read_u16(what)? as i16 as i32;
+		if count < 0 {
+			for _ in 0..-count {
+				let length = r.read_u16(what)?;
+				let replen = r.read_u16(what)?;
+				instruments.push(InstrumentRecord { length, replen, params: None });
+			}
+			count = r.read_u16(what)? as i16 as i32;
+		}
+		for _ in 0..=count {
+			let length = r.read_u16(what)?;
+			let replen = r.read_u16(what)?;
+			let mut words = [0; 9];
+			for word in &mut words {
+				*word = r.read_u16(what)?;
+			}
+			let [mpitch, mod_, bpitch, attack, dist, decay, mpitchdecay, moddecay, bpitchdecay] = words;
+			let params = WordParameters { mpitch, mod_, bpitch, attack, dist, decay, mpitchdecay, moddecay, bpitchdecay };
+			instruments.push(InstrumentRecord { length, replen, params: Some(params) });
+		}
+
+		let track_size = r.read_u16("song header")? as usize;
+		let range_size = r.read_u16("song header")? as usize;
+		if track_size == 0 || track_size & 1 != 0 || range_size % 4 != 2 {
+			return Err(anyhow::anyhow!("Invalid song header"));
+		}
+		let mut note_ranges = vec![];
+		for _ in 0 .. range_size / 4 {
+			let [note_min, count] = r.read_u16("note ranges")?.to_be_bytes();
+			let offset = r.read_u16("note ranges")?;
+			note_ranges.push(NoteRange { note_min, count, offset });
+		}
+		let music_length = track_size / 2;
+		let restart = (r.read_u16("note ranges")? as i16 as isize / 2 + music_length as isize - 1) as usize;
+		if restart >= music_length {
+			return Err(anyhow::anyhow!("Invalid restart position"));
+		}
+
+		// Tracks are stored in reverse channel order.
+		let mut tracks: [Vec<u16>; CHANNEL_COUNT] = Default::default();
+		for track in tracks.iter_mut().rev() {
+			for _ in 0..music_length {
+				track.push(r.read_u16("note data")?);
+			}
+		}
+
+		Ok(SongData { instruments, note_ranges, restart, tracks })
+	}
+
+	/// Encode the song data on the form read by the player.
+	pub fn write(&self) -> anyhow::Result<Vec<u8>> {
+		let mut data = write_instruments(&self.instruments)?;
+
+		data.extend_from_slice(&((self.length() * 2) as u16).to_be_bytes());
+		data.extend_from_slice(&((self.note_ranges.len() * 4 + 2) as u16).to_be_bytes());
+		for range in &self.note_ranges {
+			data.extend_from_slice(&[range.note_min, range.count]);
+			data.extend_from_slice(&range.offset.to_be_bytes());
+		}
+		data.extend_from_slice(&(((self.restart as isize - self.length() as isize + 1) * 2) as i16).to_be_bytes());
+		for track in self.tracks.iter().rev() {
+			track.iter().for_each(|word| data.extend_from_slice(&word.to_be_bytes()));
+		}
+		Ok(data)
+	}
+
+	/// Number of vblanks until the music loops.
+	pub fn length(&self) -> usize {
+		self.tracks[0].len()
+	}
+
+	/// Channel states for the given number of vblanks, looping at the restart position.
+	pub fn frames(&self, vblanks: usize) -> anyhow::Result<Vec<[ChannelFrame; CHANNEL_COUNT]>> {
+		let mut frames = Vec::with_capacity(vblanks);
+		let mut state = [ChannelFrame::default(); CHANNEL_COUNT];
+		let mut position = 0;
+		for vblank in 0..vblanks {
+			if position >= self.length() {
+				position = self.restart;
+			}
+			for (channel, frame) in state.iter_mut().enumerate() {
+				*frame = self.decode(self.tracks[channel][position], frame)
+					.map_err(|e| anyhow::anyhow!("{} at vblank {} channel {}", e, vblank, channel))?;
+			}
+			frames.push(state);
+			position += 1;
+		}
+		Ok(frames)
+	}
+
+	// Mirrors the channel loop in CinterPlay2.
+	fn decode(&self, word: u16, previous: &ChannelFrame) -> anyhow::Result<ChannelFrame> {
+		if word & 0x8000 != 0 {
+			let (trigger, note) = self.lookup_note_id(word & 0x1FF)
+				.ok_or_else(|| anyhow::anyhow!("Invalid note ID {}", word & 0x1FF))?;
+			let period = *PERIOD_TABLE.get(note as usize)
+				.ok_or_else(|| anyhow::anyhow!("Note out of range"))?;
+			Ok(ChannelFrame { trigger: Some(trigger), period, volume: word >> 9 & 63 })
+		} else {
+			let volume = (previous.volume + (word >> 9)) & 63;
+			// Signed 9-bit period delta
+			let delta = ((word << 7) as i16 >> 7) as u16;
+			let period = if (delta ^ (delta << 1)) & 0x80 != 0 {
+				// Bits 7 and 6 differ: absolute note
+				let index = (delta & 0xFF00 | (delta << 1) & 0xFF) as i16 >> 1;
+				*usize::try_from(index).ok().and_then(|i| PERIOD_TABLE.get(i))
+					.ok_or_else(|| anyhow::anyhow!("Note out of range"))?
+			} else {
+				previous.period.wrapping_add(delta)
+			};
+			Ok(ChannelFrame { trigger: None, period, volume })
+		}
+	}
+
+	/// Instrument, offset and note for a note ID.
+	pub fn lookup_note_id(&self, id: u16) -> Option<(Trigger, u8)> {
+		let mut instrument: Option<usize> = None;
+		let mut id = id as usize;
+		for range in &self.note_ranges {
+			if range.offset == 0 {
+				instrument = Some(instrument.map_or(0, |i| i + 1));
+			}
+			if id < range.count as usize {
+				let instrument = instrument.filter(|&i| i < self.instruments.len())?;
+				let note = range.note_min.wrapping_add(id as u8);
+				return Some((Trigger { instrument, offset: range.offset }, note));
+			}
+			id -= range.count as usize;
+		}
+		None
+	}
+}
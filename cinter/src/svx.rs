@@ -0,0 +1,257 @@
+use crate::sample::{find_chunk, read_chunks, read_text, write_chunk, SampleFile, SAMPLE_RATE};
+
+const FIBONACCI_DELTAS: [i8; 16] = [-34, -21, -13, -8, -5, -3, -2, -1, 0, 1, 2, 3, 5, 8, 13, 21];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+	None,
+	FibonacciDelta,
+}
+
+/// Voice header of an 8SVX file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Vhdr {
+	/// Samples in the non-repeating part of the highest octave.
+	pub one_shot_hi_samples: u32,
+	/// Samples in the repeating part of the highest octave.
+	pub repeat_hi_samples: u32,
+	pub samples_per_hi_cycle: u32,
+	pub samples_per_sec: u16,
+	pub octaves: u8,
+	pub compression: Compression,
+	/// Volume as 16.16 fixed point.
+	pub volume: u32,
+}
+
+impl Vhdr {
+	fn read(body: &[u8]) -> anyhow::Result<Vhdr> {
+		if body.len() < 20 {
+			return Err(anyhow::anyhow!("VHDR chunk truncated"));
+		}
+		let long = |i: usize| u32::from_be_bytes(body[i .. i + 4].try_into().unwrap());
+		if body[14] == 0 || body[14] > 16 {
+			return Err(anyhow::anyhow!("Invalid octave count {}", body[14]));
+		}
+		let compression = match body[15] {
+			0 => Compression::None,
+			1 => Compression::FibonacciDelta,
+			c => return Err(anyhow::anyhow!("Unknown compression {}", c)),
+		};
+		Ok(Vhdr {
+			one_shot_hi_samples: long(0),
+			repeat_hi_samples: long(4),
+			samples_per_hi_cycle: long(8),
+			samples_per_sec: u16::from_be_bytes([body[12], body[13]]),
+			octaves: body[14],
+			compression,
+			volume: long(16),
+		})
+	}
+
+	fn write(&self) -> Vec<u8> {
+		let mut body = vec![];
+		body.extend_from_slice(&self.one_shot_hi_samples.to_be_bytes());
+		body.extend_from_slice(&self.repeat_hi_samples.to_be_bytes());
+		body.extend_from_slice(&self.samples_per_hi_cycle.to_be_bytes());
+		body.extend_from_slice(&self.samples_per_sec.to_be_bytes());
+		body.push(self.octaves);
+		body.push(self.compression as u8);
+		body.extend_from_slice(&self.volume.to_be_bytes());
+		body
+	}
+
+	/// Samples in the highest octave.
+	pub fn hi_samples(&self) -> anyhow::Result<usize> {
+		self.one_shot_hi_samples.checked_add(self.repeat_hi_samples).map(|n| n as usize)
+			.ok_or_else(|| anyhow::anyhow!("VHDR sample counts too large"))
+	}
+
+	/// Samples in all octaves of one channel.
+	pub fn body_length(&self) -> anyhow::Result<usize> {
+		let scale = 1usize.checked_shl(self.octaves.max(1) as u32).ok_or_else(|| anyhow::anyhow!("Invalid octave count {}", self.octaves))?;
+		self.hi_samples()?.checked_mul(scale - 1).ok_or_else(|| anyhow::anyhow!("VHDR sample counts too large"))
+	}
+}
+
+/// Point of an ATAK or RLSE envelope.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EnvelopePoint {
+	/// Milliseconds to reach the volume.
+	pub duration: u16,
+	/// Volume factor as 16.16 fixed point.
+	pub dest: u32,
+}
+
+/// An 8SVX file with its samples decompressed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Svx {
+	pub header: Vhdr,
+	pub name: Option<String>,
+	pub annotation: Option<String>,
+	/// CHAN chunk: 2 for left, 4 for right, 6 for stereo.
+	pub channel: Option<u32>,
+	pub attack: Vec<EnvelopePoint>,
+	pub release: Vec<EnvelopePoint>,
+	/// All octaves of the first channel, followed by the second channel if stereo.
+	pub body: Vec<i8>,
+}
+
+fn read_envelope(body: &[u8]) -> Vec<EnvelopePoint> {
+	body.chunks_exact(6).map(|p| EnvelopePoint {
+		duration: u16::from_be_bytes([p[0], p[1]]),
+		dest: u32::from_be_bytes([p[2], p[3], p[4], p[5]]),
+	}).collect()
+}
+
+fn write_envelope(points: &[EnvelopePoint]) -> Vec<u8> {
+	points.iter().flat_map(|p| p.duration.to_be_bytes().into_iter().chain(p.dest.to_be_bytes())).collect()
+}
+
+/// Expand Fibonacci-delta data: a pad byte, the initial value, then
+/// one delta code per nibble, high nibble first.
+pub fn fibonacci_decompress(data: &[u8]) -> Vec<i8> {
+	let mut value = data.get(1).copied().unwrap_or(0) as i8;
+	let mut samples = vec![];
+	for &byte in data.iter().skip(2) {
+		for code in [byte >> 4, byte & 15] {
+			value = value.wrapping_add(FIBONACCI_DELTAS[code as usize]);
+			samples.push(value);
+		}
+	}
+	samples
+}
+
+/// Encode samples as Fibonacci-delta data, which is lossy when the
+/// deltas are not in the table.
+pub fn fibonacci_compress(samples: &[i8]) -> Vec<u8> {
+	let mut value = samples.first().copied().unwrap_or(0);
+	let mut data = vec![0, value as u8];
+	let mut codes = samples.iter().map(|&target| {
+		// Closest reachable value without wrapping
+		let code = (0..16).min_by_key(|&c| {
+			let next = value as i32 + FIBONACCI_DELTAS[c] as i32;
+			if (-128..=127).contains(&next) { (next - target as i32).abs() } else { i32::MAX }
+		}).unwrap();
+		value = value.wrapping_add(FIBONACCI_DELTAS[code]);
+		code as u8
+	}).collect::<Vec<u8>>();
+	if codes.len() & 1 != 0 {
+		codes.push(8);
+	}
+	data.extend(codes.chunks(2).map(|pair| pair[0] << 4 | pair[1]));
+	data
+}
+
+// Channels stored in the body for a CHAN chunk value.
+fn channel_count(channel: Option<u32>) -> usize {
+	if channel == Some(6) { 2 } else { 1 }
+}
+
+impl Svx {
+	pub fn read(data: &[u8]) -> anyhow::Result<Svx> {
+		let form = read_chunks(data, true)?;
+		let body = match find_chunk(&form, b"FORM") {
+			Some([b'8', b'S', b'V', b'X', body @ ..]) => body,
+			_ => return Err(anyhow::anyhow!("Not an 8SVX file")),
+		};
+		let chunks = read_chunks(body, true)?;
+		let header = Vhdr::read(find_chunk(&chunks, b"VHDR").ok_or_else(|| anyhow::anyhow!("Could not find VHDR chunk"))?)?;
+		let channel = match find_chunk(&chunks, b"CHAN") {
+			Some(chan) if chan.len() >= 4 => Some(u32::from_be_bytes(chan[0..4].try_into()?)),
+			_ => None,
+		};
+		let data = find_chunk(&chunks, b"BODY").ok_or_else(|| anyhow::anyhow!("Could not find BODY chunk"))?;
+		let body = match header.compression {
+			Compression::None => data.iter().map(|&s| s as i8).collect(),
+			// Each channel is compressed separately, with its own pad byte and initial value.
+			Compression::FibonacciDelta => {
+				let length = header.body_length()?;
+				data.chunks(data.len().div_ceil(channel_count(channel)).max(1)).flat_map(|data| {
+					let mut samples = fibonacci_decompress(data);
+					samples.truncate(length);
+					samples
+				}).collect()
+			},
+		};
+		Ok(Svx {
+			header,
+			name: find_chunk(&chunks, b"NAME").map(read_text),
+			annotation: find_chunk(&chunks, b"ANNO").map(read_text),
+			channel,
+			attack: find_chunk(&chunks, b"ATAK").map(read_envelope).unwrap_or_default(),
+			release: find_chunk(&chunks, b"RLSE").map(read_envelope).unwrap_or_default(),
+			body,
+		})
+	}
+
+	pub fn write(&self) -> Vec<u8> {
+		let mut body = b"8SVX".to_vec();
+		write_chunk(&mut body, b"VHDR", true, &self.header.write());
+		if let Some(name) = &self.name {
+			write_chunk(&mut body, b"NAME", true, name.as_bytes());
+		}
+		if let Some(annotation) = &self.annotation {
+			write_chunk(&mut body, b"ANNO", true, annotation.as_bytes());
+		}
+		if let Some(channel) = self.channel {
+			write_chunk(&mut body, b"CHAN", true, &channel.to_be_bytes());
+		}
+		if !self.attack.is_empty() {
+			write_chunk(&mut body, b"ATAK", true, &write_envelope(&self.attack));
+		}
+		if !self.release.is_empty() {
+			write_chunk(&mut body, b"RLSE", true, &write_envelope(&self.release));
+		}
+		let data: Vec<u8> = match self.header.compression {
+			Compression::None => self.body.iter().map(|&s| s as u8).collect(),
+			Compression::FibonacciDelta => {
+				let channels = channel_count(self.channel);
+				self.body.chunks(self.body.len().div_ceil(channels).max(1)).flat_map(fibonacci_compress).collect()
+			},
+		};
+		write_chunk(&mut body, b"BODY", true, &data);
+
+		let mut svx = vec![];
+		write_chunk(&mut svx, b"FORM", true, &body);
+		svx
+	}
+
+	/// Samples of an octave of the first channel, where octave 0 is the highest.
+	pub fn octave(&self, index: u8) -> Option<&[i8]> {
+		if index >= self.header.octaves.max(1) {
+			return None;
+		}
+		let hi_samples = self.header.hi_samples().ok()?;
+		let scale = 1usize.checked_shl(index as u32)?;
+		let start = hi_samples.checked_mul(scale - 1)?;
+		self.body.get(start .. start.checked_add(hi_samples.checked_mul(scale)?)?)
+	}
+
+	/// Single-octave uncompressed file for a sample.
+	pub fn from_sample(sample: &SampleFile) -> Svx {
+		Svx {
+			header: Vhdr {
+				one_shot_hi_samples: (sample.data.len() - sample.repeat_length) as u32,
+				repeat_hi_samples: sample.repeat_length as u32,
+				samples_per_hi_cycle: 32,
+				samples_per_sec: SAMPLE_RATE as u16,
+				octaves: 1,
+				compression: Compression::None,
+				volume: 0x10000,
+			},
+			name: sample.name.clone(),
+			annotation: None,
+			channel: None,
+			attack: vec![],
+			release: vec![],
+			body: sample.data.clone(),
+		}
+	}
+
+	/// The highest octave of the first channel as a sample.
+	pub fn to_sample(&self) -> anyhow::Result<SampleFile> {
+		let data = self.body.get(..self.header.hi_samples()?).ok_or_else(|| anyhow::anyhow!("BODY chunk truncated"))?.to_vec();
+		let repeat_length = (self.header.repeat_hi_samples as usize).min(data.len());
+		Ok(SampleFile { name: self.name.clone(), data, repeat_length })
+	}
+}
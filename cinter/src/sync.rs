@@ -0,0 +1,109 @@
+use std::fmt::Write;
+
+use crate::convert::{notename, Conversion, PERIOD_TABLE};
+use crate::song::SongData;
+
+// Event types in the binary table
+const NOTE: u16 = 0;
+const ROW: u16 = 1;
+const MARKER: u16 = 2;
+const END: u16 = 0xFFFF;
+
+/// Channels are numbered as in the module, whatever the layout of the song data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncEvent {
+	/// First vblank of a row.
+	Row { position: u8, pattern: u8, row: u8 },
+	/// Note triggered by the player.
+	Note { channel: u8, instrument: u8, note: u8, volume: u8 },
+	/// 8xx command, which is ignored by the player.
+	Marker { channel: u8, value: u8 },
+}
+
+impl SyncEvent {
+	// Type and channel word followed by two data words
+	fn words(&self) -> [u16; 3] {
+		match *self {
+			SyncEvent::Row { position, pattern, row } => [ROW << 8, (position as u16) << 8 | pattern as u16, row as u16],
+			SyncEvent::Note { channel, instrument, note, volume } =>
+				[NOTE << 8 | channel as u16, (instrument as u16) << 8 | note as u16, volume as u16],
+			SyncEvent::Marker { channel, value } => [MARKER << 8 | channel as u16, value as u16, 0],
+		}
+	}
+
+	fn comment(&self) -> String {
+		match *self {
+			SyncEvent::Row { position, pattern, row } => format!("Position {} pattern {} row {}", position, pattern, row),
+			SyncEvent::Note { channel, instrument, note, volume } =>
+				format!("Channel {} instrument {:02} {} volume {}", channel, instrument, notename(Some(note as i32)), volume),
+			SyncEvent::Marker { channel, value } => format!("Channel {} marker {:02X}", channel, value),
+		}
+	}
+}
+
+/// Events of the converted song by vblank, as played by the player from the
+/// start of the music until it loops.
+pub struct SyncTable {
+	pub length: usize,
+	/// Vblank the music loops back to at the end.
+	pub restart: usize,
+	/// Events sorted by vblank, with rows before notes before markers.
+	pub events: Vec<(usize, SyncEvent)>,
+}
+
+impl SyncTable {
+	pub fn new(conversion: &Conversion) -> anyhow::Result<SyncTable> {
+		let song = SongData::read(&conversion.song_data)?;
+		let numbers = conversion.instrument_numbers();
+		let mut events = vec![];
+		for &(vblank, location) in &conversion.rows {
+			events.push((vblank, SyncEvent::Row { position: location.position as u8, pattern: location.pattern as u8, row: location.row as u8 }));
+		}
+		for (vblank, frame) in song.frames(song.length())?.iter().enumerate() {
+			for (track, channel_frame) in frame.iter().enumerate() {
+				if let Some(trigger) = channel_frame.trigger {
+					let note = PERIOD_TABLE.iter().position(|&p| p == channel_frame.period).unwrap_or(0);
+					events.push((vblank, SyncEvent::Note {
+						channel: conversion.channels[track] as u8,
+						instrument: numbers.get(trigger.instrument).copied().unwrap_or(0) as u8,
+						note: note as u8,
+						volume: channel_frame.volume as u8,
+					}));
+				}
+			}
+		}
+		for &(vblank, track, value) in &conversion.markers {
+			events.push((vblank, SyncEvent::Marker { channel: track as u8, value }));
+		}
+		// Rows first, then by type and channel, keeping the order of markers in a channel.
+		events.sort_by_key(|&(vblank, event)| (vblank, event.words()[0] >> 8 != ROW, event.words()[0]));
+		Ok(SyncTable { length: song.length(), restart: song.restart, events })
+	}
+
+	/// Big-endian words: the music length and restart vblank, then four words
+	/// per event (vblank, type << 8 | channel and two data words), ending with
+	/// a vblank of $FFFF.
+	pub fn write_binary(&self) -> Vec<u8> {
+		let mut words = vec![self.length as u16, self.restart as u16];
+		for &(vblank, event) in &self.events {
+			words.push(vblank as u16);
+			words.extend(event.words());
+		}
+		words.push(END);
+		words.iter().flat_map(|word| word.to_be_bytes()).collect()
+	}
+
+	/// The binary table as assembler source with a comment for each event.
+	pub fn write_asm(&self) -> String {
+		let mut asm = String::new();
+		writeln!(asm, "\t; Music length and restart vblank").unwrap();
+		writeln!(asm, "\tdc.w\t{},{}", self.length, self.restart).unwrap();
+		writeln!(asm, "\t; Vblank, type << 8 | channel, data, data").unwrap();
+		for &(vblank, event) in &self.events {
+			let [kind, a, b] = event.words();
+			writeln!(asm, "\tdc.w\t{},${:04X},${:04X},{}\t; {}", vblank, kind, a, b, event.comment()).unwrap();
+		}
+		writeln!(asm, "\tdc.w\t${:04X}", END).unwrap();
+		asm
+	}
+}
@@ -0,0 +1,45 @@
+use crate::module::CHANNEL_COUNT;
+use crate::render::{PAULA_CLOCK, VBLANK_RATE};
+use crate::song::SongData;
+
+// Vblanks for the longest sample to play to its end at the lowest note,
+// as notes keep playing after the music stops or loops.
+const TAIL_VBLANKS: usize = 1600;
+
+/// Length in words of each instrument in the song data as far as it is
+/// played at non-zero volume, through the whole song and then through the
+/// looping part until all notes have had time to end. Instruments reaching
+/// the end of their repeat are played in full.
+pub fn played_lengths(song: &SongData) -> anyhow::Result<Vec<usize>> {
+	let mut played = vec![0; song.instruments.len()];
+	// Instrument and play position in bytes for each channel
+	let mut voices: [Option<(usize, f64)>; CHANNEL_COUNT] = [None; CHANNEL_COUNT];
+	let vblanks = song.length() + (song.length() - song.restart).max(TAIL_VBLANKS);
+	for frame in song.frames(vblanks)? {
+		for (voice, channel) in voices.iter_mut().zip(frame) {
+			if let Some(trigger) = channel.trigger {
+				// The offset must stay inside the sample even if the note is silent.
+				played[trigger.instrument] = played[trigger.instrument].max(trigger.offset as usize + 1);
+				*voice = Some((trigger.instrument, trigger.offset as f64 * 2.0));
+			}
+			let Some((instrument, position)) = voice else { continue };
+			let record = &song.instruments[*instrument];
+			let length = record.length as f64 * 2.0;
+			if channel.period != 0 {
+				*position += PAULA_CLOCK / channel.period as f64 / VBLANK_RATE as f64;
+			}
+			if channel.volume > 0 {
+				// Paula fetches one word ahead of the one it plays.
+				let words = (*position / 2.0).ceil() as usize + 1;
+				played[*instrument] = played[*instrument].max(words.min(record.length as usize));
+			}
+			if *position >= length {
+				if record.replen > 0 {
+					played[*instrument] = record.length as usize;
+				}
+				*voice = None;
+			}
+		}
+	}
+	Ok(played)
+}
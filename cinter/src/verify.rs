@@ -0,0 +1,84 @@
+use std::fmt;
+
+use crate::module::{Module, CHANNEL_COUNT};
+use crate::convert::Options;
+use crate::replay::{replay, replay_cia_tempo};
+use crate::song::{ChannelFrame, SongData, Trigger};
+
+/// First point where the song data plays differently from Protracker.
+pub struct Divergence {
+	pub vblank: usize,
+	pub position: usize,
+	pub pattern: usize,
+	pub row: usize,
+	pub channel: usize,
+	pub protracker: ChannelFrame,
+	pub song_data: ChannelFrame,
+}
+
+struct FrameText<'f>(&'f ChannelFrame);
+
+impl fmt::Display for FrameText<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if let Some(trigger) = self.0.trigger {
+			write!(f, "instrument {:02} offset {} triggered, ", trigger.instrument, trigger.offset)?;
+		}
+		write!(f, "period {} volume {}", self.0.period, self.0.volume)
+	}
+}
+
+impl fmt::Display for Divergence {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		writeln!(f, "Divergence at vblank {} in position {} pattern {} row {} track {}",
+			self.vblank, self.position, self.pattern, self.row, self.channel)?;
+		writeln!(f, "  Protracker: {}", FrameText(&self.protracker))?;
+		write!(f, "  Song data:  {}", FrameText(&self.song_data))
+	}
+}
+
+/// Compare the song data with the module played in Protracker 2.3d, through
+/// the whole song and once more through the looping part.
+/// `instrument_numbers` gives the module instrument number for each song data instrument.
+/// Channels are not compared until Protracker first triggers a note in them,
+/// nor while silent in both.
+pub fn first_divergence(module: &Module, song: &SongData, instrument_numbers: &[usize]) -> anyhow::Result<Option<Divergence>> {
+	first_divergence_with_options(module, song, instrument_numbers, &Options::default())
+}
+
+/// The same, for song data converted with the given options.
+pub fn first_divergence_with_options(module: &Module, song: &SongData, instrument_numbers: &[usize], options: &Options) -> anyhow::Result<Option<Divergence>> {
+	let vblanks = song.length() * 2 - song.restart;
+	let expected = if options.cia_tempo { replay_cia_tempo(module, vblanks) } else { replay(module, vblanks) };
+	let actual = song.frames(expected.len())?;
+	let mut started = [false; CHANNEL_COUNT];
+	for (vblank, (pt, frame)) in expected.iter().zip(&actual).enumerate() {
+		for channel in 0..CHANNEL_COUNT {
+			let mut protracker = pt.channels[channel];
+			started[channel] |= protracker.trigger.is_some();
+			if !started[channel] {
+				continue;
+			}
+			// The song data caps volumes at 63.
+			protracker.volume = protracker.volume.min(63);
+			let mut song_data = frame[channel];
+			song_data.trigger = song_data.trigger.map(|t| Trigger {
+				instrument: instrument_numbers.get(t.instrument).copied().unwrap_or(0),
+				..t
+			});
+			let silent = protracker.volume == 0 && song_data.volume == 0
+				&& protracker.trigger.is_none() && song_data.trigger.is_none();
+			if protracker != song_data && !silent {
+				return Ok(Some(Divergence {
+					vblank,
+					position: pt.position,
+					pattern: pt.pattern,
+					row: pt.row,
+					channel,
+					protracker,
+					song_data,
+				}));
+			}
+		}
+	}
+	Ok(None)
+}
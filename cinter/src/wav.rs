@@ -0,0 +1,118 @@
+use crate::sample::{find_chunk, read_chunks, read_text, write_chunk, SampleFile, SAMPLE_RATE};
+
+/// 16-bit stereo WAV file.
+pub fn stereo16(sample_rate: u32, samples: &[[i16; 2]]) -> Vec<u8> {
+	let data_size = samples.len() as u32 * 4;
+	let mut wav = vec![];
+	wav.extend_from_slice(b"RIFF");
+	wav.extend_from_slice(&(36 + data_size).to_le_bytes());
+	wav.extend_from_slice(b"WAVE");
+	wav.extend_from_slice(b"fmt ");
+	wav.extend_from_slice(&16u32.to_le_bytes());
+	wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+	wav.extend_from_slice(&2u16.to_le_bytes()); // Channels
+	wav.extend_from_slice(&sample_rate.to_le_bytes());
+	wav.extend_from_slice(&(sample_rate * 4).to_le_bytes());
+	wav.extend_from_slice(&4u16.to_le_bytes()); // Block align
+	wav.extend_from_slice(&16u16.to_le_bytes()); // Bits per sample
+	wav.extend_from_slice(b"data");
+	wav.extend_from_slice(&data_size.to_le_bytes());
+	for frame in samples {
+		for s in frame {
+			wav.extend_from_slice(&s.to_le_bytes());
+		}
+	}
+	wav
+}
+
+/// Mono WAV file with 8 or 16 bits per sample, with the loop in a smpl
+/// chunk and the name in a LIST/INFO chunk.
+pub fn write_sample(sample: &SampleFile, bits: u16) -> Vec<u8> {
+	assert!(bits == 8 || bits == 16);
+	let bytes_per_sample = bits as u32 / 8;
+	let mut body = b"WAVE".to_vec();
+
+	let mut fmt = vec![];
+	fmt.extend_from_slice(&1u16.to_le_bytes()); // PCM
+	fmt.extend_from_slice(&1u16.to_le_bytes()); // Channels
+	fmt.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+	fmt.extend_from_slice(&(SAMPLE_RATE * bytes_per_sample).to_le_bytes());
+	fmt.extend_from_slice(&(bytes_per_sample as u16).to_le_bytes()); // Block align
+	fmt.extend_from_slice(&bits.to_le_bytes());
+	write_chunk(&mut body, b"fmt ", false, &fmt);
+
+	let data: Vec<u8> = match bits {
+		// 8-bit WAV is unsigned
+		8 => sample.data.iter().map(|&s| s as u8 ^ 0x80).collect(),
+		_ => sample.data.iter().flat_map(|&s| ((s as i16) << 8).to_le_bytes()).collect(),
+	};
+	write_chunk(&mut body, b"data", false, &data);
+
+	if let Some(start) = sample.repeat_start() {
+		let mut smpl = vec![];
+		let words = [
+			0, 0, // Manufacturer, product
+			1_000_000_000 / SAMPLE_RATE, // Sample period in ns
+			60, 0, 0, 0, // MIDI note, pitch fraction, SMPTE format and offset
+			1, 0, // Loop count, sampler data
+			0, 0, start as u32, sample.data.len() as u32 - 1, 0, 0, // Forward loop with inclusive end
+		];
+		for word in words {
+			smpl.extend_from_slice(&u32::to_le_bytes(word));
+		}
+		write_chunk(&mut body, b"smpl", false, &smpl);
+	}
+
+	if let Some(name) = &sample.name {
+		let mut info = b"INFO".to_vec();
+		let mut text = name.as_bytes().to_vec();
+		text.push(0);
+		write_chunk(&mut info, b"INAM", false, &text);
+		write_chunk(&mut body, b"LIST", false, &info);
+	}
+
+	let mut wav = vec![];
+	write_chunk(&mut wav, b"RIFF", false, &body);
+	wav
+}
+
+/// Read a mono 8 or 16-bit WAV file.
+pub fn read_sample(data: &[u8]) -> anyhow::Result<SampleFile> {
+	let riff = read_chunks(data, false)?;
+	let body = match find_chunk(&riff, b"RIFF") {
+		Some([b'W', b'A', b'V', b'E', body @ ..]) => body,
+		_ => return Err(anyhow::anyhow!("Not a WAV file")),
+	};
+	let chunks = read_chunks(body, false)?;
+	let fmt = find_chunk(&chunks, b"fmt ").ok_or_else(|| anyhow::anyhow!("Could not find fmt chunk"))?;
+	if fmt.len() < 16 {
+		return Err(anyhow::anyhow!("fmt chunk truncated"));
+	}
+	let word = |bytes: &[u8], i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+	let long = |bytes: &[u8], i: usize| u32::from_le_bytes(bytes[i .. i + 4].try_into().unwrap());
+	let (format, channels, bits) = (word(fmt, 0), word(fmt, 2), word(fmt, 14));
+	if format != 1 || channels != 1 || (bits != 8 && bits != 16) {
+		return Err(anyhow::anyhow!("Only mono 8 or 16-bit PCM is supported"));
+	}
+	let pcm = find_chunk(&chunks, b"data").ok_or_else(|| anyhow::anyhow!("Could not find data chunk"))?;
+	let data: Vec<i8> = match bits {
+		8 => pcm.iter().map(|&s| (s ^ 0x80) as i8).collect(),
+		_ => pcm.chunks_exact(2).map(|s| (i16::from_le_bytes([s[0], s[1]]) >> 8) as i8).collect(),
+	};
+
+	let mut repeat_length = 0;
+	if let Some(smpl) = find_chunk(&chunks, b"smpl") {
+		if smpl.len() >= 60 && long(smpl, 28) > 0 {
+			repeat_length = data.len().saturating_sub(long(smpl, 44) as usize);
+		}
+	}
+
+	let mut name = None;
+	for (id, list) in &chunks {
+		if let (b"LIST", [b'I', b'N', b'F', b'O', info @ ..]) = (id, list) {
+			name = find_chunk(&read_chunks(info, false)?, b"INAM").map(read_text);
+		}
+	}
+
+	Ok(SampleFile { name, data, repeat_length })
+}
@@ -0,0 +1,11 @@
+use cinter::module::Module;
+
+pub fn example_data(name: &str) -> Vec<u8> {
+	std::fs::read(format!("{}/../examples/{}.mod", env!("CARGO_MANIFEST_DIR"), name)).unwrap()
+}
+
+// Not every test uses both.
+#[allow(dead_code)]
+pub fn example(name: &str) -> Module {
+	Module::read(&example_data(name)).unwrap()
+}
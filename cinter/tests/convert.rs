@@ -0,0 +1,60 @@
+mod common;
+
+use cinter::convert::{convert, convert_with_options, write_diagnostics_json, Conversion, DiagnosticCode, Location, Options, Severity};
+use cinter::render::render;
+use cinter::song::SongData;
+use common::example;
+
+#[test]
+fn diagnostics_are_located() {
+	let conversion = convert(&example("CurtCool-BackInSpace"));
+	let first = &conversion.diagnostics[0];
+	assert_eq!((first.severity, first.code), (Severity::Error, DiagnosticCode::FineslideOnNote));
+	assert_eq!(first.location, Some(Location { position: 3, pattern: 4, track: 2, row: 62 }));
+	assert_eq!(first.to_string(), "Fineslide on note in pattern 4 track 2 row 62");
+	assert_eq!(conversion.error_count(), conversion.diagnostics.len());
+
+	let mut json = String::new();
+	write_diagnostics_json(&conversion.diagnostics, &mut json).unwrap();
+	assert!(json.contains(r#""code": "fineslide-on-note", "message": "Fineslide on note", "position": 3, "pattern": 4, "track": 2, "row": 62"#));
+	assert_eq!(json.lines().count(), conversion.diagnostics.len() + 2);
+}
+
+#[test]
+fn clean_module_has_no_diagnostics() {
+	assert!(convert(&example("Wasp-Octorubber")).diagnostics.is_empty());
+}
+
+#[test]
+fn cia_tempo_is_spread_over_vblanks() {
+	let mut module = example("Wasp-Octorubber");
+	let first = module.positions[0] as usize;
+	module.patterns[first].rows[0][3].command = 0xF;
+	module.patterns[first].rows[0][3].argument = 130;
+
+	let conversion = convert(&module);
+	assert_eq!(conversion.diagnostics[0].code, DiagnosticCode::TempoSet);
+	assert_eq!(conversion.timing_deviation, None);
+
+	let conversion = convert_with_options(&module, &Options { cia_tempo: true, ..Options::default() });
+	assert!(conversion.diagnostics.is_empty());
+	assert_eq!(conversion.music_length, (3877.0 * 125.0 / 130.0f64).round() as usize);
+	assert!(conversion.timing_deviation.unwrap() <= 10.0);
+}
+
+#[test]
+fn trimmed_instruments_sound_the_same() {
+	for name in ["CurtCool-Glutenfull", "JazzCat-Automatic"] {
+		let module = example(name);
+		let full = convert(&module);
+		let trimmed = convert_with_options(&module, &Options { trim: true, ..Options::default() });
+		assert!(full.trim_size > 0 && full.trim_size == trimmed.trim_size);
+		assert_eq!(trimmed.total_inst_size, full.total_inst_size - full.trim_size);
+
+		let render = |conversion: &Conversion| {
+			let song = SongData::read(&conversion.song_data).unwrap();
+			render(&song, &conversion.raw_data, song.length() * 2 - song.restart, 8000).unwrap()
+		};
+		assert!(render(&full) == render(&trimmed), "{} sounds different when trimmed", name);
+	}
+}
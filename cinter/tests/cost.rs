@@ -0,0 +1,70 @@
+mod common;
+
+use cinter::convert::convert;
+use cinter::cost::{init_cost, instrument_cost, Cpu};
+use cinter::engine::{CinterParams, SynthVersion, WordParameters};
+use cinter::song::{InstrumentRecord, SongData};
+use common::example;
+
+fn record(steps: [u8; 12], length: u16) -> InstrumentRecord {
+	let params = WordParameters::from_params(SynthVersion::Cinter4, &CinterParams::from_steps(steps));
+	InstrumentRecord { length, replen: 0, params: Some(params) }
+}
+
+#[test]
+fn cost_follows_parameters() {
+	let plain = record([5, 40, 53, 50, 65, 50, 20, 40, 0, 0, 0, 0], 4096);
+	let distorted = record([5, 40, 53, 50, 65, 50, 20, 40, 5, 5, 5, 5], 4096);
+	let decaying = record([5, 40, 53, 30, 65, 70, 20, 40, 0, 0, 0, 0], 4096);
+	for cpu in Cpu::ALL {
+		let cost = instrument_cost(cpu, &plain);
+		assert_eq!(cost.memory, 8192);
+		assert!(instrument_cost(cpu, &distorted).cycles > cost.cycles);
+		assert!(instrument_cost(cpu, &decaying).cycles > cost.cycles);
+		assert!(instrument_cost(cpu, &record([5, 40, 53, 50, 65, 50, 20, 40, 0, 0, 0, 0], 8192)).cycles > cost.cycles * 19 / 10);
+	}
+	let raw = InstrumentRecord { length: 1000, replen: 0, params: None };
+	assert_eq!(instrument_cost(Cpu::M68000, &raw).memory, 2000);
+	assert_eq!(instrument_cost(Cpu::M68000, &raw).cycles, 0);
+}
+
+#[test]
+fn cost_matches_hand_count() {
+	let params = WordParameters {
+		mpitch: 0x1000,
+		mod_: 0x2000,
+		bpitch: 0x3000,
+		attack: 0xFF00,
+		// mdist 1, vpower 1
+		dist: 0x1010,
+		decay: 0x0010,
+		// Falling with 8 bits set
+		mpitchdecay: 0xFF00,
+		moddecay: 0,
+		// Growing with 1 bit set
+		bpitchdecay: 0x0100,
+	};
+	let cost = instrument_cost(Cpu::M68000, &InstrumentRecord { length: 4, replen: 0, params: Some(params) });
+	// Per sample: loop 580, three wave LONGMULs 3 * (24 + 2 * 38) = 300,
+	// mulu bits of the modulation strength 2 * 2 * 8 = 32, muls 46 + 2 * 8 = 62,
+	// extra mdist pass 46, extra vpower pass 30 + 62 = 92,
+	// mpitchdecay 12 + 24 + 2 * (38 + 2 * 8) = 144,
+	// bpitchdecay 12 + 24 + 2 * (38 + 2 * 1) + 12 = 128, in all 1384.
+	// 6 samples after the cleared word, with 7 bits set in the indices 0-5
+	// multiplied by the two wave LONGMULs: 190 + 6 * 1384 + 4 * 2 * 7 = 8550.
+	assert_eq!(cost.cycles, 8550);
+	assert_eq!(cost.memory, 8);
+}
+
+#[test]
+fn estimate_is_near_converter_estimate() {
+	for name in ["CurtCool-BackInSpace", "Hoffman-PaintersEuphoria", "JazzCat-Automatic", "Wasp-Octorubber"] {
+		let conversion = convert(&example(name));
+		let song = SongData::read(&conversion.song_data).unwrap();
+		let cost = init_cost(Cpu::M68000, &song.instruments);
+		assert_eq!(cost.memory, conversion.total_inst_size * 2, "{}", name);
+		let seconds = cost.seconds(Cpu::M68000);
+		assert!(seconds > conversion.total_inst_time * 0.75 && seconds < conversion.total_inst_time * 1.25, "{}: {}", name, seconds);
+		assert!(init_cost(Cpu::M68020, &song.instruments).seconds(Cpu::M68020) < seconds / 2.0);
+	}
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use std::sync::Arc;
+
+use cinter::convert::convert;
+use cinter::engine::{CinterEngine, CinterInstrument, CinterParams, EngineMode, SynthVersion, WordParameters};
+use cinter::song::SongData;
+use common::example;
+
+// The reference dumps in tests/golden are the first 2048 words produced by
+// CinterMakeInstruments in player/Cinter4.S for the instrument records that
+// the converter builds from these names.
+const CORPUS: &[&str] = &[
+	"105405350655020400012",
+	"100005350655020400000",
+	"110604130775050503200",
+	"102302970296080701124",
+	"10020XX50XX50XX50XXXX",
+	"103500150025099450509",
+	"10445030004XX60002053",
+	"10170654853523555030X",
+	"100XX0050655000500000",
+	"1XX405350655020400012",
+	"150108925178513957641",
+	"1033505500550XXXX44X6",
+];
+
+#[test]
+fn exact_mode_matches_player() {
+	let engine = Arc::new(CinterEngine::with_mode(EngineMode::Exact));
+	for name in CORPUS {
+		let path = format!("{}/tests/golden/{}.raw", env!("CARGO_MANIFEST_DIR"), name);
+		let reference = std::fs::read(&path).unwrap();
+		let params = CinterEngine::parameters_from_sample_filename(name).unwrap();
+		let mut instrument = CinterInstrument::new(engine.clone(), SynthVersion::Cinter4, &params, &[], Some(reference.len()), None);
+		let data: Vec<u8> = (0..reference.len()).map(|i| instrument.get_sample_raw(i) as u8).collect();
+		let mismatch = data.iter().zip(&reference).position(|(a, b)| a != b);
+		assert_eq!(mismatch, None, "{} differs from player output", name);
+	}
+}
+
+#[test]
+fn cinter3_names_match_song_data() {
+	let engine = Arc::new(CinterEngine::with_mode(EngineMode::Exact));
+	let module = example("JazzCat-Automatic");
+	let conversion = convert(&module);
+	let song = SongData::read(&conversion.song_data).unwrap();
+	let mut cinter3_count = 0;
+	for (record, number) in song.instruments.iter().zip(conversion.instrument_numbers()) {
+		let name = module.instrument(number).name();
+		let version = SynthVersion::from_sample_filename(&name);
+		if let (Some(words), SynthVersion::Cinter3 { .. }) = (&record.params, version) {
+			cinter3_count += 1;
+			let params = CinterEngine::parameters_from_sample_filename(&name).unwrap();
+			assert!(CinterEngine::sample_filename_from_parameters(version, &params).eq_ignore_ascii_case(&name));
+
+			let length = record.length as usize * 2;
+			let mut reference = CinterInstrument::from_words(engine.clone(), words, length, None);
+			let mut instrument = CinterInstrument::new(engine.clone(), version, &params, &[], Some(length), None);
+			let mismatch = (0..length).position(|i| instrument.get_sample_raw(i) != reference.get_sample_raw(i));
+			assert_eq!(mismatch, None, "{} differs from song data", name);
+		}
+	}
+	assert!(cinter3_count > 0);
+}
+
+#[test]
+fn parameters_give_song_data_words() {
+	let module = example("JazzCat-Automatic");
+	let conversion = convert(&module);
+	let song = SongData::read(&conversion.song_data).unwrap();
+	for (record, number) in song.instruments.iter().zip(conversion.instrument_numbers()) {
+		let name = module.instrument(number).name();
+		if let Some(words) = &record.params {
+			let params = CinterEngine::parameters_from_sample_filename(&name).unwrap();
+			let version = SynthVersion::from_sample_filename(&name);
+			assert_eq!(WordParameters::from_params(version, &params), *words, "{}", name);
+			assert_eq!(CinterParams::from_normalized(&params.to_normalized()), params);
+		}
+	}
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use cinter::convert::convert;
+use cinter::include::{asm_data, c_array, Sizes};
+use common::example;
+
+#[test]
+fn source_holds_the_data() {
+	let data: Vec<u8> = (0..37).map(|i| (i * 7) as u8).collect();
+	let mut bytes = vec![];
+	for line in asm_data("Music", &data).lines().filter_map(|line| line.strip_prefix('\t')) {
+		let (size, values) = line.split_once('\t').unwrap();
+		for value in values.split(',') {
+			let value = u16::from_str_radix(value.trim_start_matches('$'), 16).unwrap();
+			match size {
+				"dc.w" => bytes.extend(value.to_be_bytes()),
+				_ => bytes.push(value as u8),
+			}
+		}
+	}
+	assert_eq!(bytes, data);
+
+	let c = c_array("music", &data);
+	assert!(c.starts_with("const unsigned short music[19] = {\n\t0x0007, 0x0E15,"));
+	assert!(c.ends_with("0xFC00,\n};\n"));
+}
+
+#[test]
+fn header_gives_the_sizes() {
+	let conversion = convert(&example("JazzCat-Automatic"));
+	let sizes = Sizes::new(&conversion);
+	assert_eq!(sizes.music, conversion.song_data.len());
+	assert_eq!(sizes.instrument_space, conversion.total_inst_size * 2);
+
+	let asm = sizes.asm_header("automatic");
+	assert!(asm.contains("AUTOMATIC_WORK_SIZE\tequ\t33180\t"));
+	assert!(asm.contains("AUTOMATIC_WORK_SIZE_MANUAL_DMA\tequ\t33176\t"));
+	assert!(asm.contains(&format!("AUTOMATIC_INSTRUMENT_SPACE\tequ\t{}\t", sizes.instrument_space)));
+	let c = sizes.c_header("automatic", &["automatic"]);
+	assert!(c.contains(&format!("#define AUTOMATIC_MUSIC_SIZE {} ", sizes.music)));
+	assert!(c.contains("extern const unsigned short automatic[];"));
+}
@@ -0,0 +1,37 @@
+mod common;
+
+use cinter::convert::convert;
+use cinter::layout::{apply_layout, layout_data, Layout};
+use cinter::pack::estimate_packed_size;
+use cinter::render::render;
+use cinter::song::SongData;
+use common::example;
+
+#[test]
+fn playable_layouts_sound_the_same() {
+	let conversion = convert(&example("JazzCat-Automatic"));
+	assert!(layout_data(&conversion, &Layout::DEFAULT).unwrap() == conversion.song_data);
+
+	let song = SongData::read(&conversion.song_data).unwrap();
+	let expected = render(&song, &conversion.raw_data, 2000, 8000).unwrap();
+	for layout in Layout::all().into_iter().filter(|layout| layout.is_playable()) {
+		let mut arranged = convert(&example("JazzCat-Automatic"));
+		apply_layout(&mut arranged, &layout).unwrap();
+		let song = SongData::read(&arranged.song_data).unwrap();
+		assert!(render(&song, &arranged.raw_data, 2000, 8000).unwrap() == expected, "{} sounds different", layout);
+	}
+}
+
+#[test]
+fn repetitive_data_packs_better() {
+	let repetitive: Vec<u8> = (0..10000).map(|i| (i % 37) as u8).collect();
+	let mut state = 1u32;
+	let noisy: Vec<u8> = (0..10000).map(|_| {
+		state ^= state << 13;
+		state ^= state >> 17;
+		state ^= state << 5;
+		state as u8
+	}).collect();
+	assert!(estimate_packed_size(&repetitive) < 100);
+	assert!(estimate_packed_size(&noisy) > 9000);
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use cinter::module::{Module, MAX_SAMPLE_LENGTH};
+use cinter::sample::SampleFile;
+use common::example_data;
+
+#[test]
+fn round_trip_is_identical() {
+	for name in ["CurtCool-BackInSpace", "Hoffman-PaintersEuphoria", "JazzCat-Automatic", "Wasp-Octorubber"] {
+		let data = example_data(name);
+		let module = Module::read(&data).unwrap();
+		assert!(module.write() == data, "{} changed by round trip", name);
+	}
+}
+
+#[test]
+fn truncation_is_reported() {
+	let data = example_data("Wasp-Octorubber");
+	for length in [0, 100, 1084, 2000, data.len() - 1] {
+		assert!(Module::read(&data[..length]).is_err(), "No error for {} bytes", length);
+	}
+}
+
+#[test]
+fn sample_is_replaced() {
+	let mut module = Module::read(&example_data("JazzCat-Automatic")).unwrap();
+	let sample = SampleFile {
+		name: Some("1054050065502040000X2".to_string()),
+		data: (0..1001).map(|i| (i % 200) as i8).collect(),
+		repeat_length: 201,
+	};
+	let volume = module.instrument(1).volume;
+	module.instrument_mut(1).set_sample(&sample).unwrap();
+	let module = Module::read(&module.write()).unwrap();
+	let instrument = module.instrument(1);
+	assert_eq!(instrument.name(), "1054050065502040000X2");
+	assert_eq!(instrument.volume, volume);
+	assert_eq!(instrument.length(), 501);
+	assert_eq!((instrument.repeat_offset, instrument.repeat_length), (400, 101));
+	let read = instrument.to_sample();
+	assert_eq!(read.name, sample.name);
+	assert_eq!(&read.data[..1001], &sample.data[..]);
+	assert_eq!(read.repeat_length, 202);
+
+	let mut instrument = instrument.clone();
+	instrument.set_sample(&SampleFile { repeat_length: 0, ..sample.clone() }).unwrap();
+	assert_eq!((instrument.repeat_offset, instrument.repeat_length), (0, 1));
+	assert_eq!(instrument.to_sample().repeat_length, 0);
+
+	// Longer samples do not fit in the 16-bit word length.
+	assert!(instrument.set_sample(&SampleFile { data: vec![0; MAX_SAMPLE_LENGTH + 2], ..sample.clone() }).is_err());
+	assert_eq!(instrument.to_sample().repeat_length, 0);
+	instrument.set_sample(&SampleFile { data: vec![0; MAX_SAMPLE_LENGTH], ..sample }).unwrap();
+	let mut long = Module::read(&example_data("JazzCat-Automatic")).unwrap();
+	*long.instrument_mut(1) = instrument;
+	let read = Module::read(&long.write()).unwrap();
+	assert_eq!(read.instrument(1).samples.len(), MAX_SAMPLE_LENGTH);
+	assert!(read.write() == long.write());
+}
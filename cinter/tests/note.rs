@@ -0,0 +1,83 @@
+use std::f64::consts::PI;
+use std::sync::{Arc, RwLock};
+
+use cinter::engine::{CinterEngine, CinterInstrument, CinterParams, SynthVersion};
+use cinter::paula::{Amiga, AmigaModel};
+use cinter::{Interpolation, Note};
+
+const SAMPLE_RATE: f32 = 44100.0;
+
+// Pure sine with 16 samples per cycle: instant attack, no decay, no modulation or distortion.
+// The last four cycles are repeated.
+fn sine_instrument() -> Arc<RwLock<CinterInstrument>> {
+	let params = CinterParams::from_steps([0, 100, 0, 50, 77, 50, 0, 50, 0, 0, 0, 0]);
+	let engine = Arc::new(CinterEngine::new());
+	Arc::new(RwLock::new(CinterInstrument::new(engine, SynthVersion::Cinter4, &params, &[], Some(4096), Some(4096 - 64))))
+}
+
+fn play(key: u8, interpolation: Interpolation, count: usize) -> Vec<f64> {
+	let mut note = Note::with_interpolation(sine_instrument(), key, 127, SAMPLE_RATE, interpolation);
+	// Skip the attack
+	(0..count + 1000).map(|_| note.produce_sample() as f64).skip(1000).collect()
+}
+
+fn rms(samples: &[f64]) -> f64 {
+	(samples.iter().map(|s| s * s).sum::<f64>() / samples.len() as f64).sqrt()
+}
+
+// Fraction of the signal not explained by a sine at the given frequency.
+fn distortion(samples: &[f64], freq: f64) -> f64 {
+	let w = 2.0 * PI * freq / SAMPLE_RATE as f64;
+	let (mut s, mut c) = (0.0, 0.0);
+	for (i, v) in samples.iter().enumerate() {
+		s += v * (w * i as f64).sin();
+		c += v * (w * i as f64).cos();
+	}
+	let (s, c) = (s * 2.0 / samples.len() as f64, c * 2.0 / samples.len() as f64);
+	let residual: Vec<f64> = samples.iter().enumerate()
+		.map(|(i, v)| v - s * (w * i as f64).sin() - c * (w * i as f64).cos())
+		.collect();
+	rms(&residual) / rms(samples)
+}
+
+fn sine_freq(key: u8) -> f64 {
+	440.0 * ((key + 27) as f64 / 12.0).exp2() / 16.0
+}
+
+#[test]
+fn interpolation_quality_is_ordered() {
+	// 880 Hz, with the instrument played at about a third of the output rate
+	let key = 33;
+	let count = SAMPLE_RATE as usize / 4;
+	let nearest = distortion(&play(key, Interpolation::Nearest, count), sine_freq(key));
+	let linear = distortion(&play(key, Interpolation::Linear, count), sine_freq(key));
+	let cubic = distortion(&play(key, Interpolation::Cubic, count), sine_freq(key));
+	let sinc = distortion(&play(key, Interpolation::Sinc, count), sine_freq(key));
+	assert!(nearest > linear && linear > cubic, "{} {} {}", nearest, linear, cubic);
+	assert!(sinc < 0.01, "{}", sinc);
+	for interpolation in [Interpolation::Nearest, Interpolation::Linear, Interpolation::Cubic, Interpolation::Sinc] {
+		let level = rms(&play(key, interpolation, count)) * 2f64.sqrt();
+		assert!((level - 1.0).abs() < 0.02, "{}: {}", interpolation.name(), level);
+	}
+}
+
+#[test]
+fn sinc_suppresses_aliasing() {
+	// The sine is at 28.2kHz, above the output Nyquist frequency, so nothing should be heard.
+	let key = 93;
+	let count = SAMPLE_RATE as usize / 20;
+	let cubic = rms(&play(key, Interpolation::Cubic, count));
+	let sinc = rms(&play(key, Interpolation::Sinc, count));
+	assert!(cubic > 0.1, "{}", cubic);
+	assert!(sinc < cubic * 0.05, "{} {}", sinc, cubic);
+}
+
+#[test]
+fn paula_plays_at_full_level() {
+	// Protracker A-2, at 3546895 / 254 / 16 = 873 Hz
+	let interpolation = Interpolation::Paula(Amiga { model: AmigaModel::A1200, led_filter: false });
+	let samples = play(33, interpolation, SAMPLE_RATE as usize / 4);
+	let level = rms(&samples) * 2f64.sqrt();
+	assert!((level - 1.0).abs() < 0.05, "{}", level);
+	assert!(distortion(&samples, 3546895.0 / 254.0 / 16.0) < 0.2);
+}
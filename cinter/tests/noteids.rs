@@ -0,0 +1,80 @@
+mod common;
+
+use cinter::convert::convert;
+use cinter::module::Module;
+use cinter::noteids::{apply_exact_fixes, id_count, id_ranges, suggestions, Fix, IdRange};
+use common::example;
+
+#[test]
+fn duplicated_instrument_is_merged() {
+	let original = example("CurtCool-Svumpukkel");
+	let original_count = convert(&original).note_id_count;
+
+	// Play instrument 3 from a copy in slot 31 in every other pattern.
+	let mut module = original.clone();
+	*module.instrument_mut(31) = module.instrument(3).clone();
+	for pattern in module.patterns.iter_mut().step_by(2) {
+		for track_row in pattern.rows.iter_mut().flatten() {
+			if track_row.instrument == 3 {
+				track_row.instrument = 31;
+			}
+		}
+	}
+	let conversion = convert(&module);
+	let ranges = id_ranges(&conversion).unwrap();
+	assert_eq!(id_count(&ranges), conversion.note_id_count);
+	assert!(conversion.note_id_count > original_count);
+
+	let suggestion = &suggestions(&module, &ranges)[0];
+	assert_eq!(suggestion.fix, Fix::Merge { from: 31, into: 3 });
+	let applied = apply_exact_fixes(&mut module, &ranges);
+	assert_eq!(applied.len(), 1);
+	assert_eq!(convert(&module).note_id_count, original_count);
+	assert!(convert(&module).song_data == convert(&original).song_data);
+}
+
+fn range(instrument: usize, offset: u8, note_min: u8, note_max: u8) -> IdRange {
+	IdRange { instrument, offset, note_min, note_max }
+}
+
+#[test]
+fn cinter_instruments_are_compared_by_name() {
+	let mut module = example("CurtCool-Svumpukkel");
+	let fixes = |module: &Module, from: usize, into: usize| {
+		suggestions(module, &[range(into, 0, 0, 12), range(from, 0, 0, 12)]).into_iter().map(|s| s.fix).collect::<Vec<_>>()
+	};
+
+	// Instruments 4 and 5 differ only in their names.
+	module.instrument_mut(5).samples = module.instrument(4).samples.clone();
+	assert!(fixes(&module, 5, 4).is_empty());
+
+	// Stale sample data does not matter, as the converter regenerates it.
+	*module.instrument_mut(30) = module.instrument(4).clone();
+	module.instrument_mut(30).samples.iter_mut().for_each(|s| *s = 0);
+	assert_eq!(fixes(&module, 30, 4), vec![Fix::Merge { from: 30, into: 4 }]);
+}
+
+#[test]
+fn raw_copy_is_transposed_and_offsets_collapsed() {
+	let mut module = example("CurtCool-Svumpukkel");
+	let samples: Vec<i8> = (0..400).map(|i| ((i * 37) % 251) as i8).collect();
+	for (number, step) in [(30, 1), (31, 2)] {
+		let inst = module.instrument_mut(number);
+		inst.set_name("raw");
+		inst.volume = 64;
+		inst.samples = samples.iter().step_by(step).copied().collect();
+	}
+
+	let ranges = [range(30, 0, 10, 20), range(31, 0, 0, 10)];
+	let suggestion = &suggestions(&module, &ranges)[0];
+	assert_eq!(suggestion.fix, Fix::Transpose { from: 31, into: 30 });
+	assert!(!suggestion.fix.is_exact());
+	assert_eq!(suggestion.fix.apply_to_ranges(&ranges), vec![range(30, 0, 10, 22)]);
+	assert_eq!(suggestion.saved, 22 - 13);
+
+	let ranges = [range(30, 0, 0, 10), range(30, 2, 5, 15), range(30, 8, 0, 10)];
+	let fixes: Vec<Fix> = suggestions(&module, &ranges).into_iter().map(|s| s.fix).collect();
+	assert_eq!(fixes, vec![Fix::CollapseOffset { instrument: 30, from: 2, into: 0 }]);
+	assert_eq!(fixes[0].apply_to_ranges(&ranges), vec![range(30, 0, 0, 15), range(30, 8, 0, 10)]);
+	assert!(apply_exact_fixes(&mut module, &ranges).is_empty());
+}
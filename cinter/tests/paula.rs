@@ -0,0 +1,62 @@
+use std::f32::consts::PI;
+
+use cinter::convert::PERIOD_TABLE;
+use cinter::paula::{period_for_key, Amiga, AmigaModel, PaulaVoice};
+
+const SAMPLE_RATE: f32 = 44100.0;
+
+// One second of a looped 32-byte sine sample through a Paula voice.
+fn play(period: u16, amiga: Option<Amiga>) -> Vec<f32> {
+	let sine: Vec<i8> = (0..32).map(|i| ((i as f32 / 32.0 * 2.0 * PI).sin() * 127.0).round() as i8).collect();
+	let mut voice = PaulaVoice::new(period, SAMPLE_RATE, amiga);
+	(0..SAMPLE_RATE as usize).map(|_| voice.produce_sample(|i| sine[i % 32])).collect()
+}
+
+fn peak(samples: &[f32]) -> f32 {
+	samples[samples.len() / 2..].iter().fold(0.0, |m, s| m.max(s.abs()))
+}
+
+#[test]
+fn keys_play_protracker_periods() {
+	assert_eq!(period_for_key(12), 856);
+	assert_eq!(period_for_key(47), 113);
+	assert_eq!((12..48).map(period_for_key).collect::<Vec<_>>(), PERIOD_TABLE);
+	assert_eq!(period_for_key(0), 1712);
+	assert_eq!(period_for_key(48), 107);
+}
+
+#[test]
+fn voice_plays_at_period_rate() {
+	let samples = play(428, None);
+	// Count periods with some hysteresis, as held zeros ring slightly around zero
+	let mut high = false;
+	let mut crossings = 0;
+	for &s in &samples {
+		if !high && s > 64.0 {
+			crossings += 1;
+		}
+		high = if high { s > -64.0 } else { s > 64.0 };
+	}
+	// 3546895 / 428 / 32 = 259 Hz
+	assert!((258..=260).contains(&crossings), "{} crossings", crossings);
+	assert!((peak(&samples) - 127.0).abs() < 4.0);
+}
+
+#[test]
+fn filters_attenuate_high_tones() {
+	let a500 = Amiga { model: AmigaModel::A500, led_filter: false };
+	let a500_led = Amiga { model: AmigaModel::A500, led_filter: true };
+	let a1200 = Amiga { model: AmigaModel::A1200, led_filter: false };
+	// 3546895 / 124 / 32 = 894 Hz passes the A500 filter
+	let low = peak(&play(124, Some(a500)));
+	assert!(low > 110.0, "{}", low);
+	// A 3575 Hz square loses its harmonics, and with the LED filter some of its fundamental
+	let square: Vec<i8> = (0..8).map(|i| if i < 4 { 100 } else { -100 }).collect();
+	let play_square = |amiga| {
+		let mut voice = PaulaVoice::new(124, SAMPLE_RATE, Some(amiga));
+		let samples: Vec<f32> = (0..SAMPLE_RATE as usize).map(|_| voice.produce_sample(|i| square[i % 8])).collect();
+		peak(&samples)
+	};
+	let (bright, dull, duller) = (play_square(a1200), play_square(a500), play_square(a500_led));
+	assert!(bright > dull && dull > duller, "{} {} {}", bright, dull, duller);
+}
@@ -0,0 +1,21 @@
+mod common;
+
+use cinter::module::Module;
+use cinter::refresh::{refresh, stale_instruments};
+use common::example;
+
+#[test]
+fn refreshed_module_matches_names() {
+	let original = example("Wasp-Octorubber");
+	let mut module = original.clone();
+	let changed: Vec<usize> = refresh(&mut module).iter().map(|s| s.number).collect();
+	assert_eq!(changed, stale_instruments(&original).iter().map(|s| s.number).collect::<Vec<_>>());
+	assert!(stale_instruments(&module).is_empty());
+	let module = Module::read(&module.write()).unwrap();
+	for (before, after) in original.instruments.iter().zip(&module.instruments) {
+		assert_eq!(before.name(), after.name());
+		assert_eq!(before.samples.len(), after.samples.len());
+		assert_eq!((before.volume, before.finetune), (after.volume, after.finetune));
+		assert_eq!((before.repeat_offset, before.repeat_length), (after.repeat_offset, after.repeat_length));
+	}
+}
@@ -0,0 +1,108 @@
+mod common;
+
+use cinter::convert::{convert, convert_with_options, DiagnosticCode, Location, Options};
+use cinter::song::SongData;
+use cinter::verify::{first_divergence, first_divergence_with_options};
+use common::example;
+
+#[test]
+fn conversion_matches_protracker() {
+	for name in ["Hoffman-PaintersEuphoria", "JazzCat-Automatic", "Wasp-Octorubber"] {
+		let module = example(name);
+		let conversion = convert(&module);
+		let song = SongData::read(&conversion.song_data).unwrap();
+		let divergence = first_divergence(&module, &song, &conversion.instrument_numbers()).unwrap();
+		assert!(divergence.is_none(), "{}: {}", name, divergence.unwrap());
+	}
+}
+
+#[test]
+fn fineslide_on_note_diverges() {
+	// E21 on a note, which the converter reports as an error and ignores
+	let module = example("CurtCool-BackInSpace");
+	let conversion = convert(&module);
+	let song = SongData::read(&conversion.song_data).unwrap();
+	let divergence = first_divergence(&module, &song, &conversion.instrument_numbers()).unwrap().unwrap();
+	assert_eq!((divergence.pattern, divergence.row, divergence.channel), (4, 62, 2));
+	assert_eq!((divergence.protracker.period, divergence.song_data.period), (191, 190));
+}
+
+#[test]
+fn offset_carried_over_diverges() {
+	// Notes with 9xx and no instrument number, which Protracker plays from the
+	// sample start left by the previous 9xx, where it added the offset twice
+	let module = example("CurtCool-SailHoSpaceship");
+	let conversion = convert(&module);
+	let first = &conversion.diagnostics[0];
+	assert_eq!(first.code, DiagnosticCode::OffsetCarriedOver);
+	assert_eq!(first.location, Some(Location { position: 1, pattern: 1, track: 1, row: 18 }));
+	let song = SongData::read(&conversion.song_data).unwrap();
+	let divergence = first_divergence(&module, &song, &conversion.instrument_numbers()).unwrap().unwrap();
+	assert_eq!((divergence.pattern, divergence.row, divergence.channel), (1, 18, 1));
+	let offsets = (divergence.protracker.trigger.unwrap().offset, divergence.song_data.trigger.unwrap().offset);
+	assert_eq!(offsets, (16384, 8192));
+}
+
+#[test]
+fn vibrato_and_tremolo_match_protracker() {
+	let mut module = example("Wasp-Octorubber");
+	let commands = [(0x4, 0x46), (0x7, 0x8C), (0xE, 0x41), (0x6, 0x02), (0x4, 0x00), (0xE, 0x72), (0x7, 0x00), (0xC, 0x20)];
+	let mut n = 0;
+	for pattern in &mut module.patterns {
+		for row in &mut pattern.rows {
+			for tr in row.iter_mut() {
+				if tr.command == 0 && tr.argument == 0 {
+					n += 1;
+					if n % 3 == 0 {
+						(tr.command, tr.argument) = commands[n / 3 % commands.len()];
+					}
+				}
+			}
+		}
+	}
+	let conversion = convert(&module);
+	assert!(conversion.diagnostics.is_empty(), "{}", conversion.diagnostics[0]);
+	let song = SongData::read(&conversion.song_data).unwrap();
+	let divergence = first_divergence(&module, &song, &conversion.instrument_numbers()).unwrap();
+	assert!(divergence.is_none(), "{}", divergence.unwrap());
+}
+
+#[test]
+fn pattern_loops_match_protracker() {
+	let mut module = example("Wasp-Octorubber");
+	let first = module.positions[0] as usize;
+	let rows = &mut module.patterns[first].rows;
+	// Nested loops, a loop repeated by pattern delay and a loop followed by a break
+	for (row, channel, argument) in [(4, 0, 0x60), (8, 1, 0x60), (11, 1, 0x62), (13, 0, 0x62),
+			(20, 2, 0x60), (22, 2, 0x63), (22, 3, 0xE1), (40, 0, 0x60), (41, 0, 0x61)] {
+		rows[row][channel].command = 0xE;
+		rows[row][channel].argument = argument;
+	}
+	rows[41][1].command = 0xD;
+	rows[41][1].argument = 0x10;
+	let conversion = convert(&module);
+	assert!(conversion.diagnostics.is_empty(), "{}", conversion.diagnostics[0]);
+	let song = SongData::read(&conversion.song_data).unwrap();
+	let divergence = first_divergence(&module, &song, &conversion.instrument_numbers()).unwrap();
+	assert!(divergence.is_none(), "{}", divergence.unwrap());
+}
+
+#[test]
+fn cia_tempo_matches_protracker() {
+	let mut module = example("Wasp-Octorubber");
+	let first = module.positions[0] as usize;
+	let second = module.positions[1] as usize;
+	// Slower and faster than the vblank, so ticks are both stretched and merged
+	module.patterns[first].rows[0][3].command = 0xF;
+	module.patterns[first].rows[0][3].argument = 100;
+	module.patterns[second].rows[8][3].command = 0xF;
+	module.patterns[second].rows[8][3].argument = 170;
+	let options = Options { cia_tempo: true, ..Options::default() };
+	let conversion = convert_with_options(&module, &options);
+	assert!(conversion.diagnostics.is_empty(), "{}", conversion.diagnostics[0]);
+	let song = SongData::read(&conversion.song_data).unwrap();
+	let divergence = first_divergence_with_options(&module, &song, &conversion.instrument_numbers(), &options).unwrap();
+	assert!(divergence.is_none(), "{}", divergence.unwrap());
+	// Played one tick per vblank, the song is out of step after the first tempo change.
+	assert!(first_divergence(&module, &song, &conversion.instrument_numbers()).unwrap().is_some());
+}
@@ -0,0 +1,39 @@
+use cinter::sample::SampleFile;
+use cinter::{aiff, wav};
+
+fn sample(name: Option<&str>, repeat_length: usize) -> SampleFile {
+	SampleFile {
+		name: name.map(|n| n.to_string()),
+		data: (0..301).map(|i| (i * 37 % 256) as u8 as i8).collect(),
+		repeat_length,
+	}
+}
+
+#[test]
+fn wav_round_trip() {
+	for original in [sample(Some("1054050065502040000X2"), 100), sample(Some("odd"), 0), sample(None, 0)] {
+		for bits in [8, 16] {
+			let data = wav::write_sample(&original, bits);
+			assert_eq!(&data[0..4], b"RIFF");
+			assert_eq!(u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize, data.len() - 8);
+			assert_eq!(wav::read_sample(&data).unwrap(), original);
+		}
+	}
+	assert!(wav::read_sample(&aiff::write_sample(&sample(None, 0), 8)).is_err());
+	assert!(wav::read_sample(b"RIFF").is_err());
+}
+
+#[test]
+fn aiff_round_trip() {
+	for original in [sample(Some("1054050065502040000X2"), 100), sample(Some("odd"), 0), sample(None, 0)] {
+		for bits in [8, 16] {
+			let data = aiff::write_sample(&original, bits);
+			assert_eq!(&data[0..4], b"FORM");
+			assert_eq!(u32::from_be_bytes(data[4..8].try_into().unwrap()) as usize, data.len() - 8);
+			// Sample rate as 80-bit float
+			assert_eq!(&data[20 + 8 .. 20 + 18], &[0x40, 0x0D, 0x82, 0xAC, 0, 0, 0, 0, 0, 0]);
+			assert_eq!(aiff::read_sample(&data).unwrap(), original);
+		}
+	}
+	assert!(aiff::read_sample(&wav::write_sample(&sample(None, 0), 8)).is_err());
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use cinter::convert::convert;
+use cinter::engine::WordParameters;
+use cinter::render::{render, VBLANK_RATE};
+use cinter::song::{write_instruments, InstrumentRecord, SongData};
+use common::example;
+
+#[test]
+fn converted_song_is_decoded() {
+	for name in ["Hoffman-PaintersEuphoria", "Wasp-Octorubber"] {
+		let conversion = convert(&example(name));
+		let song = SongData::read(&conversion.song_data).unwrap();
+		assert!(song.write().unwrap() == conversion.song_data, "{}: song data changed by round trip", name);
+		assert_eq!(song.length(), conversion.music_length, "{}", name);
+		assert_eq!(song.restart, conversion.restart, "{}", name);
+		let used = conversion.instruments.iter().filter(|inst| inst.usage.is_some()).count();
+		assert_eq!(song.instruments.len(), used, "{}", name);
+
+		let frames = song.frames(song.length()).unwrap();
+		for report in &conversion.instruments {
+			if let Some(usage) = &report.usage {
+				let triggers = frames.iter().flatten()
+					.filter(|frame| frame.trigger.map(|t| t.instrument) == Some(usage.index))
+					.count();
+				assert!(triggers > 0, "{}: instrument {} never triggered", name, report.number);
+			}
+		}
+	}
+}
+
+#[test]
+fn render_length() {
+	let conversion = convert(&example("Wasp-Octorubber"));
+	let song = SongData::read(&conversion.song_data).unwrap();
+	let samples = render(&song, &conversion.raw_data, 100, 44100).unwrap();
+	assert_eq!(samples.len(), 100 * 44100 / VBLANK_RATE as usize);
+	assert!(samples.iter().any(|&[left, right]| left != 0 && right != 0));
+}
+
+#[test]
+fn instruments_are_encoded_as_converted() {
+	for name in ["Hoffman-PaintersEuphoria", "Wasp-Octorubber", "JazzCat-Automatic"] {
+		let conversion = convert(&example(name));
+		let song = SongData::read(&conversion.song_data).unwrap();
+		let data = write_instruments(&song.instruments).unwrap();
+		assert_eq!(data, conversion.song_data[..data.len()], "{}", name);
+	}
+
+	let raw = InstrumentRecord { length: 100, replen: 0, params: None };
+	let generated = InstrumentRecord { length: 200, replen: 50, params: Some(WordParameters::default()) };
+	assert_eq!(write_instruments(&[raw, generated]).unwrap().len(), 2 + 4 + 2 + 22);
+	assert!(write_instruments(&[generated, raw]).is_err());
+	assert!(write_instruments(&[raw]).is_err());
+	assert!(write_instruments(&[generated; 32]).is_ok());
+	assert!(write_instruments(&[generated; 33]).is_err());
+}
@@ -0,0 +1,105 @@
+use cinter::sample::SampleFile;
+use cinter::svx::{fibonacci_compress, fibonacci_decompress, Compression, EnvelopePoint, Svx};
+
+fn sample() -> SampleFile {
+	SampleFile {
+		name: Some("1054050065502040000X2".to_string()),
+		data: (0..300).map(|i| ((i as f32 * 0.1).sin() * 100.0) as i8).collect(),
+		repeat_length: 100,
+	}
+}
+
+#[test]
+fn sample_round_trip() {
+	let original = sample();
+	let data = Svx::from_sample(&original).write();
+	assert_eq!(u32::from_be_bytes(data[4..8].try_into().unwrap()) as usize, data.len() - 8);
+	assert_eq!(Svx::read(&data).unwrap().to_sample().unwrap(), original);
+
+	// Odd-length names are padded to an even size, not a multiple of 4
+	let mut svx = Svx::from_sample(&SampleFile { name: Some("1234".to_string()), ..original.clone() });
+	assert_eq!(svx.write().len() + 2, Svx::from_sample(&SampleFile { name: Some("12345".to_string()), ..original.clone() }).write().len());
+	svx.name = Some("12345".to_string());
+	assert_eq!(Svx::read(&svx.write()).unwrap().to_sample().unwrap().data, original.data);
+}
+
+#[test]
+fn octaves_and_envelopes_round_trip() {
+	let mut svx = Svx::from_sample(&sample());
+	svx.header.octaves = 3;
+	svx.body = (0..300 * 7).map(|i| (i % 251) as i8).collect();
+	svx.annotation = Some("Cinter".to_string());
+	svx.channel = Some(2);
+	svx.attack = vec![EnvelopePoint { duration: 10, dest: 0x10000 }];
+	svx.release = vec![EnvelopePoint { duration: 100, dest: 0 }, EnvelopePoint { duration: 5, dest: 0x8000 }];
+	let read = Svx::read(&svx.write()).unwrap();
+	assert_eq!(read, svx);
+	assert_eq!(read.octave(1).unwrap(), &svx.body[300..900]);
+	assert_eq!(read.octave(2).unwrap().len(), 1200);
+	assert_eq!(read.octave(3), None);
+}
+
+#[test]
+fn fibonacci_delta_round_trip() {
+	// Known data: initial value 10, then +1, -1, +21, -34
+	assert_eq!(fibonacci_decompress(&[0, 10, 0x97, 0xF0]), vec![11, 10, 31, -3]);
+
+	let mut svx = Svx::from_sample(&sample());
+	svx.header.compression = Compression::FibonacciDelta;
+	let data = svx.write();
+	let read = Svx::read(&data).unwrap();
+	assert_eq!(read.body.len(), svx.body.len());
+	for (a, b) in read.body.iter().zip(&svx.body) {
+		assert!((*a as i32 - *b as i32).abs() <= 2, "{} {}", a, b);
+	}
+	assert!(fibonacci_compress(&svx.body).len() < svx.body.len() / 2 + 3);
+}
+
+#[test]
+fn stereo_fibonacci_delta_round_trip() {
+	// Each channel has its own pad byte and initial value.
+	let mut svx = Svx::from_sample(&sample());
+	svx.header.compression = Compression::FibonacciDelta;
+	svx.channel = Some(6);
+	let right: Vec<i8> = (0..300).map(|i| ((i as f32 * 0.07).cos() * -90.0) as i8).collect();
+	svx.body.extend(&right);
+	let data = svx.write();
+	let body = &data[data.len() - 2 * 152..];
+	assert_eq!(&body[152..154], &[0, right[0] as u8]);
+	let read = Svx::read(&data).unwrap();
+	assert_eq!(read.body.len(), 600);
+	for (a, b) in read.body.iter().zip(&svx.body) {
+		assert!((*a as i32 - *b as i32).abs() <= 2, "{} {}", a, b);
+	}
+}
+
+#[test]
+fn broken_files_are_rejected() {
+	let data = Svx::from_sample(&sample()).write();
+	assert!(Svx::read(&[]).is_err());
+	assert!(Svx::read(b"FORM").is_err());
+	assert!(Svx::read(&data[..data.len() - 1]).is_err());
+	assert!(Svx::read(&data[..40]).is_err());
+}
+
+#[test]
+fn malformed_headers_are_rejected() {
+	// The VHDR body follows the FORM, 8SVX and VHDR headers.
+	let patched = |offset: usize, bytes: &[u8], compression: Compression| {
+		let mut svx = Svx::from_sample(&sample());
+		svx.header.compression = compression;
+		let mut data = svx.write();
+		data[20 + offset .. 20 + offset + bytes.len()].copy_from_slice(bytes);
+		data
+	};
+	for compression in [Compression::None, Compression::FibonacciDelta] {
+		assert!(Svx::read(&patched(14, &[0], compression)).is_err());
+		assert!(Svx::read(&patched(14, &[17], compression)).is_err());
+	}
+	let counts = [0x80, 0, 0, 0, 0x80, 0, 0, 0];
+	assert!(Svx::read(&patched(0, &counts, Compression::FibonacciDelta)).is_err());
+	let read = Svx::read(&patched(0, &counts, Compression::None)).unwrap();
+	assert!(read.to_sample().is_err());
+	assert_eq!(read.octave(0), None);
+	assert!(read.header.body_length().is_err());
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use cinter::convert::convert;
+use cinter::layout::{apply_layout, Layout};
+use cinter::song::SongData;
+use cinter::sync::{SyncEvent, SyncTable};
+use common::example;
+
+#[test]
+fn sync_events_follow_the_song() {
+	let mut module = example("JazzCat-Automatic");
+	let first_pattern = module.positions[0] as usize;
+	let row = &mut module.patterns[first_pattern].rows[4][2];
+	row.command = 0x8;
+	row.argument = 0x42;
+	let conversion = convert(&module);
+	let table = SyncTable::new(&conversion).unwrap();
+
+	assert_eq!(table.events[0], (0, SyncEvent::Row { position: 0, pattern: first_pattern as u8, row: 0 }));
+	let song = SongData::read(&conversion.song_data).unwrap();
+	let triggers = song.frames(song.length()).unwrap().iter().flatten().filter(|channel| channel.trigger.is_some()).count();
+	let notes = table.events.iter().filter(|(_, event)| matches!(event, SyncEvent::Note { .. })).count();
+	assert_eq!(notes, triggers);
+
+	let row_vblank = table.events.iter()
+		.find(|(_, event)| *event == SyncEvent::Row { position: 0, pattern: first_pattern as u8, row: 4 })
+		.unwrap().0;
+	assert!(table.events.contains(&(row_vblank, SyncEvent::Marker { channel: 2, value: 0x42 })));
+	assert!(table.events.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+	assert_eq!(table.write_binary().len(), 4 + table.events.len() * 8 + 2);
+}
+
+#[test]
+fn sync_events_use_module_channels_in_any_layout() {
+	let mut module = example("JazzCat-Automatic");
+	let first_pattern = module.positions[0] as usize;
+	let row = &mut module.patterns[first_pattern].rows[4][0];
+	row.command = 0x8;
+	row.argument = 0x42;
+	let mut conversion = convert(&module);
+	let table = SyncTable::new(&conversion).unwrap();
+
+	apply_layout(&mut conversion, &Layout { channels: [3, 1, 2, 0], ..Layout::DEFAULT }).unwrap();
+	assert_eq!(conversion.channels, [3, 1, 2, 0]);
+	assert_eq!(SyncTable::new(&conversion).unwrap().events, table.events);
+}
@@ -1,9 +1,11 @@
 use std::fs::File;
 use std::io::prelude::*;
 use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::mpsc::{sync_channel, SyncSender};
-use std::sync::{Arc, RwLock};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
 
 use eframe::egui;
 use egui::{Event, Key};
@@ -11,14 +13,27 @@ use rand::{thread_rng, Rng};
 
 use cpal::traits::{DeviceTrait, HostTrait, EventLoopTrait};
 
-use cinter::engine::{CinterEngine, CinterInstrument, PARAMETER_COUNT};
-
-use crate::iff::{IffReader, IffWriter};
+use cinter::cost::{instrument_cost, Cpu};
+use cinter::engine::{CinterEngine, CinterInstrument, CinterParams, EngineMode, SynthVersion, WordParameters, PARAMETER_COUNT};
+use cinter::module::{Instrument, Module, INSTRUMENT_COUNT};
+use cinter::sample::SampleFile;
+use cinter::song::InstrumentRecord;
+use cinter::svx::Svx;
+use cinter::{aiff, wav, Interpolation};
 
 pub const TITLE: &'static str = "Cinter 4.1 by Blueberry";
 
+// Output format when there is no audio device
+const HEADLESS_SAMPLE_RATE: f32 = 44100.0;
+const HEADLESS_BUFFER_TIME: Duration = Duration::from_millis(10);
+
+type Recording = Arc<Mutex<Option<Vec<[f32; 2]>>>>;
+
 pub struct CinterApp {
 	player: SyncSender<PlayerMessage>,
+	sample_rate: f32,
+	audio_error: Option<String>,
+	recording: Recording,
 	cursors: Vec<Arc<AtomicUsize>>,
 
 	params: CinterParameters,
@@ -28,12 +43,22 @@ pub struct CinterApp {
 	current_instrument: CinterInstrument,
 	octaves: Octaves,
 	volume: f32,
+	interpolation: Interpolation,
+	module: Option<ModuleFile>,
 
 	error_string: Option<String>,
 }
 
+// Module dropped onto the window, with the slot being edited.
+struct ModuleFile {
+	path: PathBuf,
+	module: Module,
+	slot: Option<usize>,
+}
+
 pub struct CinterParameters {
-	values: [f32; PARAMETER_COUNT],
+	version: SynthVersion,
+	values: CinterParams,
 	chord: Vec<u8>,
 	length: usize,
 	repeat_length: usize,
@@ -47,16 +72,43 @@ fn chord_text(num_tones: usize) -> String {
 	}
 }
 
+fn slot_text(number: usize, instrument: &Instrument) -> String {
+	let name = instrument.name();
+	let kind = match CinterEngine::chord_parameters_from_sample_filename(&name) {
+		Ok(..) if matches!(SynthVersion::from_sample_filename(&name), SynthVersion::Cinter3 { .. }) => "Cinter 3",
+		Ok((_, chord)) if !chord.is_empty() => "Cinter 4 chord",
+		Ok(..) => "Cinter 4",
+		Err(..) if instrument.samples.is_empty() => "Empty",
+		Err(..) => "Raw",
+	};
+	format!("{:2}: {:14} {}", number, kind, name)
+}
+
 #[derive(Clone, Copy, Eq, PartialEq)]
 enum Octaves { Low, High }
 
-enum FileFormat { Raw, Iff }
+#[derive(Clone, Copy)]
+enum FileFormat { Raw, Iff, Wav8, Wav16, Aiff }
 
 impl FileFormat {
+	const ALL: [FileFormat; 5] = [FileFormat::Raw, FileFormat::Iff, FileFormat::Wav8, FileFormat::Wav16, FileFormat::Aiff];
+
+	fn name(&self) -> &'static str {
+		match self {
+			FileFormat::Raw => "RAW",
+			FileFormat::Iff => "8SVX",
+			FileFormat::Wav8 => "8-bit WAV",
+			FileFormat::Wav16 => "16-bit WAV",
+			FileFormat::Aiff => "AIFF",
+		}
+	}
+
 	fn extension(&self) -> &'static str {
 		match self {
 			FileFormat::Raw => ".raw",
 			FileFormat::Iff => ".8svx",
+			FileFormat::Wav8 | FileFormat::Wav16 => ".wav",
+			FileFormat::Aiff => ".aiff",
 		}
 	}
 }
@@ -66,6 +118,9 @@ struct PlayerState {
 	notes: Vec<(cinter::Note, Option<Arc<AtomicUsize>>)>,
 	target_volume: f32,
 	current_volume: f32,
+	interpolation: Interpolation,
+	sample_rate: f32,
+	recording: Recording,
 }
 
 enum PlayerMessage {
@@ -73,6 +128,77 @@ enum PlayerMessage {
 	NoteOn { key: u8, cursor: Arc<AtomicUsize> },
 	NoteOff { key: u8 },
 	SetVolume { volume: f32 },
+	SetInterpolation { interpolation: Interpolation },
+}
+
+impl PlayerState {
+	fn receive(&mut self, receiver: &Receiver<PlayerMessage>) {
+		for msg in receiver.try_iter() {
+			match msg {
+				PlayerMessage::Instrument { instrument } => {
+					match &mut self.instrument {
+						Some(irc) => *irc.write().unwrap() = instrument,
+						None => self.instrument = Some(Arc::new(RwLock::new(instrument))),
+					}
+				},
+				PlayerMessage::NoteOn { key, cursor } => {
+					if !self.notes.iter().any(|(note, _)| note.key == key) {
+						if let Some(irc) = &mut self.instrument {
+							let note = cinter::Note::with_interpolation(Arc::clone(irc), key, 127, self.sample_rate, self.interpolation);
+							self.notes.push((note, Some(cursor)));
+						}
+					}
+				},
+				PlayerMessage::NoteOff { key } => {
+					for (note, _) in &mut self.notes {
+						if note.key == key {
+							note.release(127);
+						}
+					}
+				},
+				PlayerMessage::SetVolume { volume } => {
+					self.target_volume = volume;
+				},
+				PlayerMessage::SetInterpolation { interpolation } => {
+					self.interpolation = interpolation;
+				},
+			}
+		}
+	}
+
+	// Mix the playing notes into an interleaved stereo buffer.
+	fn mix(&mut self, buffer: &mut [f32]) {
+		for i in 0..buffer.len() {
+			buffer[i] = 0.0;
+		}
+		let instrument = &mut self.instrument;
+		let mut volume = self.current_volume;
+		let target_volume = self.target_volume;
+		self.notes.retain_mut(|(note, cursor)| {
+			for i in (0..buffer.len()).step_by(2) {
+				let value = note.produce_sample() * volume;
+				buffer[i + 0] += value;
+				buffer[i + 1] += value;
+				if volume != target_volume {
+					if volume < target_volume {
+						volume = target_volume.min(volume + 0.01);
+					} else {
+						volume = target_volume.max(volume - 0.01);
+					}
+				}
+			}
+			if let Some(index) = instrument.as_ref().unwrap().read().unwrap().repeated_index(note.current_index()) {
+				if let Some(cursor) = cursor { cursor.store(index, Ordering::Relaxed); }
+			} else {
+				*cursor = None;
+			}
+			note.is_alive()
+		});
+		self.current_volume = volume;
+		if let Some(frames) = self.recording.lock().unwrap().as_mut() {
+			frames.extend(buffer.chunks(2).map(|frame| [frame[0], frame[1]]));
+		}
+	}
 }
 
 fn translate_key(key: Key) -> Option<u8> {
@@ -96,22 +222,25 @@ impl CinterApp {
 			cc.egui_ctx.set_visuals(egui::Visuals::dark());
 		}
 
-		let player = Self::start_player();
-		let engine = Arc::new(CinterEngine::new());
-		let params = [
-			0.05, 0.40, 0.53, 0.50, 0.65, 0.50, 0.20, 0.40, 0.0, 0.0, 0.1, 0.2
-		];
+		let recording = Arc::new(Mutex::new(None));
+		let (player, sample_rate, audio_error) = Self::start_player(Arc::clone(&recording));
+		let engine = Arc::new(CinterEngine::with_mode(EngineMode::Exact));
+		let params = CinterParams::default();
 		let chord = vec![];
 
-		let mut current_instrument = CinterInstrument::new(Arc::clone(&engine), &params, &chord, None, None);
+		let mut current_instrument = CinterInstrument::new(Arc::clone(&engine), SynthVersion::Cinter4, &params, &chord, None, None);
 		player.send(PlayerMessage::Instrument { instrument: current_instrument.clone() }).ok();
 		let length = Self::compute_length(&mut current_instrument);
 
 		Self {
 			player,
+			sample_rate,
+			audio_error,
+			recording,
 			cursors: vec![],
 
 			params: CinterParameters {
+				version: SynthVersion::Cinter4,
 				values: params,
 				chord,
 				length,
@@ -123,6 +252,8 @@ impl CinterApp {
 			current_instrument,
 			octaves: Octaves::High,
 			volume: 0.5,
+			interpolation: Interpolation::Cubic,
+			module: None,
 
 			error_string: None,
 		}
@@ -142,8 +273,11 @@ impl CinterApp {
 		}
 	}
 
-	fn start_player() -> SyncSender<PlayerMessage> {
+	// Start the player thread. Without an audio device, the player runs
+	// silently in real time and returns the reason.
+	fn start_player(recording: Recording) -> (SyncSender<PlayerMessage>, f32, Option<String>) {
 		let (sender, receiver) = sync_channel(3);
+		let (status_sender, status) = sync_channel(1);
 
 		std::thread::spawn(move || {
 			let mut state = PlayerState {
@@ -151,139 +285,169 @@ impl CinterApp {
 				notes: vec![],
 				target_volume: 1.0,
 				current_volume: 1.0,
+				interpolation: Interpolation::Cubic,
+				sample_rate: HEADLESS_SAMPLE_RATE,
+				recording,
 			};
 
-			let host = cpal::default_host();
-			let device = host.default_output_device().expect("No output device available");
-			let format = device.default_output_format().expect("No default output format");
-			let event_loop = host.event_loop();
-			let stream = event_loop.build_output_stream(&device, &format).expect("Failed to create stream");
-			let sample_rate = format.sample_rate.0 as f32;
-			event_loop.play_stream(stream).expect("Failed to play stream");
-			event_loop.run(move |_stream_id, stream_result| {
-				let data = stream_result.expect("Error in stream");
-				if let cpal::StreamData::Output { buffer: cpal::UnknownTypeOutputBuffer::F32(mut buffer) } = data {
-					for msg in receiver.try_iter() {
-						match msg {
-							PlayerMessage::Instrument { instrument } => {
-								match &mut state.instrument {
-									Some(irc) => *irc.write().unwrap() = instrument,
-									None => state.instrument = Some(Arc::new(RwLock::new(instrument))),
-								}
-							},
-							PlayerMessage::NoteOn { key, cursor } => {
-								if !state.notes.iter().any(|(note, _)| note.key == key) {
-									if let Some(irc) = &mut state.instrument {
-										let note = cinter::Note::new(Arc::clone(irc), key, 127, sample_rate);
-										state.notes.push((note, Some(cursor)));
-									}
-								}
-							},
-							PlayerMessage::NoteOff { key } => {
-								for (note, _) in &mut state.notes {
-									if note.key == key {
-										note.release(127);
-									}
-								}
-							},
-							PlayerMessage::SetVolume { volume } => {
-								state.target_volume = volume;
-							},
+			match Self::open_output() {
+				Ok((event_loop, sample_rate)) => {
+					state.sample_rate = sample_rate;
+					status_sender.send((sample_rate, None)).ok();
+					event_loop.run(move |_stream_id, stream_result| {
+						let data = stream_result.expect("Error in stream");
+						if let cpal::StreamData::Output { buffer: cpal::UnknownTypeOutputBuffer::F32(mut buffer) } = data {
+							state.receive(&receiver);
+							state.mix(&mut buffer);
 						}
-					}
-
-					for i in 0..buffer.len() {
-						buffer[i] = 0.0;
-					}
-					let instrument = &mut state.instrument;
-					let mut volume = state.current_volume;
-					let target_volume = state.target_volume;
-					state.notes.retain_mut(|(note, cursor)| {
-						for i in (0..buffer.len()).step_by(2) {
-							let value = note.produce_sample() * volume;
-							buffer[i + 0] += value;
-							buffer[i + 1] += value;
-							if volume != target_volume {
-								if volume < target_volume {
-									volume = target_volume.min(volume + 0.01);
-								} else {
-									volume = target_volume.max(volume - 0.01);
-								}
-							}
-						}
-						if let Some(index) = instrument.as_ref().unwrap().read().unwrap().repeated_index(note.current_index()) {
-							if let Some(cursor) = cursor { cursor.store(index, Ordering::Relaxed); }
-						} else {
-							*cursor = None;
-						}
-						note.is_alive()
 					});
-					state.current_volume = volume;
-				}
-			});
+				},
+				Err(err) => {
+					status_sender.send((HEADLESS_SAMPLE_RATE, Some(format!("No sound: {}", err)))).ok();
+					let start = Instant::now();
+					let mut produced = 0;
+					loop {
+						std::thread::sleep(HEADLESS_BUFFER_TIME);
+						let due = (start.elapsed().as_secs_f32() * HEADLESS_SAMPLE_RATE) as usize;
+						let mut buffer = vec![0.0; (due - produced) * 2];
+						state.receive(&receiver);
+						state.mix(&mut buffer);
+						produced = due;
+					}
+				},
+			}
 		});
 
-		sender
+		let (sample_rate, error) = status.recv().unwrap_or((HEADLESS_SAMPLE_RATE, Some("No sound".to_string())));
+		(sender, sample_rate, error)
+	}
+
+	fn open_output() -> anyhow::Result<(cpal::EventLoop, f32)> {
+		let host = cpal::default_host();
+		let device = host.default_output_device().ok_or_else(|| anyhow::anyhow!("No output device available"))?;
+		let format = device.default_output_format()?;
+		let event_loop = host.event_loop();
+		let stream = event_loop.build_output_stream(&device, &format)?;
+		event_loop.play_stream(stream)?;
+		Ok((event_loop, format.sample_rate.0 as f32))
+	}
+
+	fn toggle_recording(&mut self) -> anyhow::Result<()> {
+		let frames = self.recording.lock().unwrap().take();
+		match frames {
+			None => *self.recording.lock().unwrap() = Some(vec![]),
+			Some(frames) => {
+				let filename = CinterEngine::sample_filename_from_chord_parameters(self.params.version, &self.params.values, &self.params.chord);
+				let samples: Vec<[i16; 2]> = frames.iter()
+					.map(|frame| frame.map(|v| (v * 32767.0).round().clamp(-32768.0, 32767.0) as i16))
+					.collect();
+				std::fs::write(filename + "-preview.wav", wav::stereo16(self.sample_rate as u32, &samples))?;
+			},
+		}
+		Ok(())
+	}
+
+	fn sample_file(&mut self) -> SampleFile {
+		SampleFile {
+			name: Some(CinterEngine::sample_filename_from_chord_parameters(self.params.version, &self.params.values, &self.params.chord)),
+			data: (0..self.params.length).map(|i| self.current_instrument.get_sample(i)).collect(),
+			repeat_length: self.params.repeat_length,
+		}
 	}
 
 	fn save_sample(&mut self, format: FileFormat) -> std::io::Result<()> {
-		let filename = CinterEngine::sample_filename_from_chord_parameters(&self.params.values, &self.params.chord);
-		let mut file = File::create(filename.clone() + format.extension())?;
-		let data: Vec<u8> = (0..self.params.length).map(|i| self.current_instrument.get_sample(i) as u8).collect();
+		let filename = CinterEngine::sample_filename_from_chord_parameters(self.params.version, &self.params.values, &self.params.chord);
+		let mut file = File::create(filename + format.extension())?;
+		let sample = self.sample_file();
+		let data: Vec<u8> = sample.data.iter().map(|&s| s as u8).collect();
 		match format {
 			FileFormat::Raw => file.write_all(&data),
+			FileFormat::Wav8 => file.write_all(&wav::write_sample(&sample, 8)),
+			FileFormat::Wav16 => file.write_all(&wav::write_sample(&sample, 16)),
+			FileFormat::Aiff => file.write_all(&aiff::write_sample(&sample, 16)),
 			FileFormat::Iff => {
-				let mut w = IffWriter::new();
-				w.write_chunk("FORM", |w| {
-					w.write_bytes("8SVX");
-					w.write_chunk("VHDR", |w| {
-						w.write_u32((self.params.length - self.params.repeat_length) as u32); // oneShotHiSamples
-						w.write_u32(self.params.repeat_length as u32); // repeatHiSamples
-						w.write_u32(32); // samplesPerHiCycle
-						w.write_u16(16726); // samplesPerSec
-						w.write_u8(1); // ctOctave
-						w.write_u8(0); // sCompression
-						w.write_u32(0x10000); // volume
-					});
-					w.write_chunk("NAME", |w| {
-						w.write_string_padded(filename.as_str());
-					});
-					w.write_chunk("ANNO", |w| {
-						w.write_string_padded(TITLE);
-					});
-					w.write_chunk("BODY", |w| {
-						w.write_bytes(data);
-					});
-				});
-				file.write_all(w.get_data())
+				let mut svx = Svx::from_sample(&sample);
+				svx.annotation = Some(TITLE.to_string());
+				file.write_all(&svx.write())
 			},
 		}
 	}
 
-	fn load_sample(&mut self, filename: &str) -> anyhow::Result<CinterParameters> {
+	fn load_file(&mut self, path: &Path) -> anyhow::Result<()> {
 		let mut data = vec![];
-		File::open(filename)?.read_to_end(&mut data)?;
-		if let Ok([b'8', b'S', b'V', b'X', chunks @ ..]) = IffReader::find_chunk(&data, "FORM") {
-			// 8SVX file
-			let header = IffReader::find_chunk(chunks, "VHDR")?;
-			let once_length = u32::from_be_bytes(header[0..4].try_into()?) as usize;
-			let repeat_length = u32::from_be_bytes(header[4..8].try_into()?) as usize;
-			let length = once_length + repeat_length;
-			let name = match IffReader::find_chunk(chunks, "NAME") {
-				Ok(name) => std::str::from_utf8(name)?,
-				_ => filename,
+		File::open(path)?.read_to_end(&mut data)?;
+		if let Ok(module) = Module::read(&data) {
+			self.module = Some(ModuleFile { path: path.to_path_buf(), module, slot: None });
+			return Ok(());
+		}
+		let filename = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+		let params = self.load_sample(filename, &data)?;
+		self.set_params(params);
+		Ok(())
+	}
+
+	fn set_params(&mut self, params: CinterParameters) {
+		self.params = params;
+		self.current_instrument = CinterInstrument::new(
+			self.engine.clone(), self.params.version, &self.params.values, &self.params.chord, None, None
+		);
+		self.auto_length = self.params.length == Self::compute_length(&mut self.current_instrument);
+	}
+
+	// Edit the instrument in a module slot, or just select the slot if it is not a Cinter instrument.
+	fn select_slot(&mut self, number: usize) {
+		let module_file = self.module.as_mut().unwrap();
+		module_file.slot = Some(number);
+		let sample = module_file.module.instrument(number).to_sample();
+		if let Some(name) = &sample.name {
+			if let Ok((param_values, chord_intervals)) = CinterEngine::chord_parameters_from_sample_filename(name) {
+				self.set_params(CinterParameters {
+					version: SynthVersion::from_sample_filename(name),
+					values: param_values,
+					chord: chord_intervals,
+					length: sample.data.len(),
+					repeat_length: sample.repeat_length,
+				});
+			}
+		}
+	}
+
+	fn write_slot(&mut self) -> anyhow::Result<()> {
+		let sample = self.sample_file();
+		let module_file = self.module.as_mut().unwrap();
+		let number = module_file.slot.ok_or_else(|| anyhow::anyhow!("No slot selected"))?;
+		module_file.module.instrument_mut(number).set_sample(&sample)
+	}
+
+	fn save_module(&self) -> anyhow::Result<()> {
+		let module_file = self.module.as_ref().unwrap();
+		std::fs::write(&module_file.path, module_file.module.write())?;
+		Ok(())
+	}
+
+	fn load_sample(&self, filename: &str, data: &[u8]) -> anyhow::Result<CinterParameters> {
+		let form_type = data.get(8..12);
+		if data.starts_with(b"RIFF") || form_type == Some(&b"AIFF"[..]) || form_type == Some(&b"8SVX"[..]) {
+			// WAV, AIFF or 8SVX file
+			let sample = match form_type {
+				Some(b"AIFF") => aiff::read_sample(data)?,
+				Some(b"8SVX") => Svx::read(data)?.to_sample()?,
+				_ => wav::read_sample(data)?,
 			};
+			let name = sample.name.as_deref().unwrap_or(filename);
 			let (param_values, chord_intervals) = CinterEngine::chord_parameters_from_sample_filename(name)?;
 			Ok(CinterParameters {
+				version: SynthVersion::from_sample_filename(name),
 				values: param_values,
 				chord: chord_intervals,
-				length,
-				repeat_length,
+				length: sample.data.len(),
+				repeat_length: sample.repeat_length,
 			})
 		} else {
 			// RAW file
 			let (param_values, chord_intervals) = CinterEngine::chord_parameters_from_sample_filename(filename)?;
 			Ok(CinterParameters {
+				version: SynthVersion::from_sample_filename(filename),
 				values: param_values,
 				chord: chord_intervals,
 				length: data.len(),
@@ -294,12 +458,23 @@ impl CinterApp {
 
 	fn set_random_parameters(&mut self) {
 		let mut random = thread_rng();
-		for p in 0..PARAMETER_COUNT {
-			self.params.values[p] = random.gen::<f32>()
-		}
+		self.params.version = SynthVersion::Cinter4;
+		self.params.values = CinterParams::from_normalized(&random.gen());
 		self.params.repeat_length = 0;
 	}
 
+	fn precalc_text(&self) -> String {
+		let record = InstrumentRecord {
+			length: (self.params.length / 2) as u16,
+			replen: (self.params.repeat_length / 2) as u16,
+			params: Some(WordParameters::from_params(self.params.version, &self.params.values)),
+		};
+		let times: Vec<String> = Cpu::ALL.iter()
+			.map(|&cpu| format!("{:.2}s on {}", instrument_cost(cpu, &record).seconds(cpu), cpu.name()))
+			.collect();
+		format!("Precalc: {}", times.join(", "))
+	}
+
 	fn compute_length(instrument: &mut CinterInstrument) -> usize {
 		let mut length = 65534usize;
 		while length > 2 && instrument.get_sample_raw(length - 1) == 0 {
@@ -320,6 +495,7 @@ impl eframe::App for CinterApp {
 	fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
 		egui::CentralPanel::default().show(ctx, |ui| {
 
+			let old_version = self.params.version;
 			let old_params = self.params.values;
 			let old_chord = self.params.chord.clone();
 			let old_length = self.params.length;
@@ -335,9 +511,15 @@ impl eframe::App for CinterApp {
 				});
 				if ui.button("Random melodic").clicked() {
 					self.set_random_parameters();
-					self.params.values[3] = 0.5;
-					self.params.values[5] = 0.5;
-					self.params.values[7] *= 0.5;
+					self.params.values.mpitchdecay = 50;
+					self.params.values.bpitchdecay = 50;
+					self.params.values.moddecay /= 2;
+				}
+				if let SynthVersion::Cinter3 { .. } = self.params.version {
+					if ui.button("Convert to Cinter 4").clicked() {
+						self.params.values = self.params.version.cinter4_parameters(&self.params.values);
+						self.params.version = SynthVersion::Cinter4;
+					}
 				}
 				ui.with_layout(egui::Layout::right_to_left(), |ui| {
 					egui::widgets::global_dark_light_mode_buttons(ui);
@@ -346,22 +528,26 @@ impl eframe::App for CinterApp {
 			ui.separator();
 
 			for p in 0..PARAMETER_COUNT {
-				let param = &mut self.params.values[p];
-				let resolution = CinterEngine::get_parameter_resolution(p as i32);
+				let values = &mut self.params.values;
 				let enabled = self.params.chord.is_empty() || (p != 3 && p != 5);
 				ui.add_enabled_ui(enabled, |ui| {
 					ui.horizontal(|ui| {
-						let (value, label) = CinterEngine::get_parameter_text_and_label(p as i32, *param);
+						let (value, label) = CinterEngine::get_parameter_text_and_label(self.params.version, p as i32, values.normalized(p));
 						ui.spacing_mut().slider_width = 400.0;
 						with_width(ui, 100.0, |ui| {
 							ui.label(CinterEngine::get_parameter_name(p as i32));
 						});
-						ui.add(egui::Slider::new(param, 0.0..=1.0).show_value(false));
+						ui.add(egui::Slider::from_get_set(0.0..=1.0, |value| {
+							if let Some(value) = value {
+								values.set_normalized(p, value as f32);
+							}
+							values.normalized(p) as f64
+						}).show_value(false));
 						if ui.small_button("➖").clicked() {
-							*param = (((*param / resolution).round() - 1.0) * resolution).max(0.0);
+							values.set_step(p, values.step(p).saturating_sub(1));
 						}
 						if ui.small_button("➕").clicked() {
-							*param = (((*param / resolution).round() + 1.0) * resolution).min(1.0);
+							values.set_step(p, values.step(p) + 1);
 						}
 						ui.label(value + " " + &label);
 					});
@@ -371,6 +557,8 @@ impl eframe::App for CinterApp {
 			ui.separator();
 
 			ui.horizontal(|ui| {
+				// Chords are a Cinter 4 feature
+				ui.set_enabled(self.params.version == SynthVersion::Cinter4);
 				let chord = &mut self.params.chord;
 				let mut num_tones = chord.len() + 1;
 				ui.add(egui::Label::new(egui::RichText::new("Chord: ").text_style(egui::TextStyle::Button)));
@@ -400,24 +588,65 @@ impl eframe::App for CinterApp {
 			ui.separator();
 
 			ui.horizontal(|ui| {
-				if ui.button("Save as RAW").clicked() {
-					match self.save_sample(FileFormat::Raw) {
-						Ok(..) => self.error_string = None,
-						Err(err) => self.error_string = Some(format!("{}", err)),
+				for format in FileFormat::ALL {
+					if ui.button(format!("Save as {}", format.name())).clicked() {
+						match self.save_sample(format) {
+							Ok(..) => self.error_string = None,
+							Err(err) => self.error_string = Some(format!("{}", err)),
+						}
 					}
 				}
-				if ui.button("Save as 8SVX").clicked() {
-					match self.save_sample(FileFormat::Iff) {
+				let recording = self.recording.lock().unwrap().is_some();
+				if ui.selectable_label(recording, "Record preview").clicked() {
+					match self.toggle_recording() {
 						Ok(..) => self.error_string = None,
 						Err(err) => self.error_string = Some(format!("{}", err)),
 					}
 				}
-				ui.add(egui::Label::new(CinterEngine::sample_filename_from_chord_parameters(&self.params.values, &self.params.chord)));
+				ui.add(egui::Label::new(CinterEngine::sample_filename_from_chord_parameters(self.params.version, &self.params.values, &self.params.chord)));
+				ui.add(egui::Label::new(self.precalc_text()));
 				if let Some(err) = &self.error_string {
 					ui.add(egui::Label::new(egui::RichText::new(err).color(egui::Color32::RED)));
 				}
 			});
 
+			if let Some(module_file) = &self.module {
+				ui.separator();
+
+				let slot = module_file.slot;
+				let slot_texts: Vec<String> = (1..=INSTRUMENT_COUNT).map(|i| slot_text(i, module_file.module.instrument(i))).collect();
+				let file_name = module_file.path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+				let mut selected = slot;
+				ui.horizontal(|ui| {
+					ui.add(egui::Label::new(egui::RichText::new(format!("Module {}: ", file_name)).text_style(egui::TextStyle::Button)));
+					egui::ComboBox::from_id_source("slot")
+						.width(300.0)
+						.selected_text(slot.map(|i| slot_texts[i - 1].as_str()).unwrap_or("Select slot"))
+						.show_ui(ui, |ui| {
+							for (i, text) in slot_texts.iter().enumerate() {
+								ui.selectable_value(&mut selected, Some(i + 1), text.as_str());
+							}
+						});
+					ui.add_enabled_ui(slot.is_some(), |ui| {
+						if ui.button("Write to slot").clicked() {
+							match self.write_slot() {
+								Ok(..) => self.error_string = None,
+								Err(err) => self.error_string = Some(format!("{}", err)),
+							}
+						}
+					});
+					if ui.button("Save module").clicked() {
+						match self.save_module() {
+							Ok(..) => self.error_string = None,
+							Err(err) => self.error_string = Some(format!("{}", err)),
+						}
+					}
+				});
+				if let Some(number) = selected.filter(|&number| Some(number) != slot) {
+					self.select_slot(number);
+				}
+			}
+
 			ui.separator();
 
 			let (plot_col, loop_col, cursor_col) = if ui.style().visuals.dark_mode {
@@ -530,6 +759,25 @@ impl eframe::App for CinterApp {
 						self.player.send(PlayerMessage::SetVolume { volume: self.volume }).ok();
 					}
 				});
+
+				ui.group(|ui| {
+					ui.add(egui::Label::new(egui::RichText::new("Interpolation: ").text_style(egui::TextStyle::Button)));
+					let interpolation = self.interpolation;
+					egui::ComboBox::from_id_source("interpolation")
+						.selected_text(self.interpolation.name())
+						.show_ui(ui, |ui| {
+							for p in Interpolation::ALL {
+								ui.selectable_value(&mut self.interpolation, p, p.name());
+							}
+						});
+					if self.interpolation != interpolation {
+						self.player.send(PlayerMessage::SetInterpolation { interpolation: self.interpolation }).ok();
+					}
+				});
+
+				if let Some(err) = &self.audio_error {
+					ui.add(egui::Label::new(egui::RichText::new(err).color(egui::Color32::RED)));
+				}
 			});
 
 			ui.with_layout(egui::Layout::bottom_up(egui::Align::Center), |ui| {
@@ -537,31 +785,23 @@ impl eframe::App for CinterApp {
 			});
 
 			for file in &ctx.input().raw.dropped_files {
-				if let Some(name) = file.path.as_ref().and_then(|f| f.file_name()).and_then(|n| n.to_str()) {
-					match self.load_sample(name) {
-						Ok(params) => {
-							self.error_string = None;
-							self.params = params;
-							self.current_instrument = CinterInstrument::new(
-								self.engine.clone(), &self.params.values, &self.params.chord, None, None
-							);
-							self.auto_length = self.params.length == Self::compute_length(&mut self.current_instrument);
-						},
-						Err(err) => {
-							self.error_string = Some(format!("{}", err));
-						},
+				if let Some(path) = &file.path {
+					match self.load_file(path) {
+						Ok(..) => self.error_string = None,
+						Err(err) => self.error_string = Some(format!("{}", err)),
 					}
 				}
 			}
 
 			if self.params.length != old_length ||
 					self.repeat_start() != old_repeat_start ||
+					self.params.version != old_version ||
 					self.params.values != old_params ||
 					self.params.chord != old_chord ||
 					self.auto_length != old_auto_length {
 				self.error_string = None;
 				self.current_instrument = CinterInstrument::new(
-					self.engine.clone(), &self.params.values, &self.params.chord, Some(self.params.length), self.repeat_start()
+					self.engine.clone(), self.params.version, &self.params.values, &self.params.chord, Some(self.params.length), self.repeat_start()
 				);
 				if self.auto_length {
 					self.params.length = Self::compute_length(&mut self.current_instrument);
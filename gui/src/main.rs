@@ -1,5 +1,4 @@
 mod app;
-mod iff;
 
 use eframe::{NativeOptions, run_native};
 use eframe::egui::Vec2;
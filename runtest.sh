@@ -1,8 +1,10 @@
 #!/bin/bash
+# Set CONVERT to run another converter, e.g. cinter/target/release/cinter-convert
+CONVERT=${CONVERT:-convert/CinterConvert.py}
 for mod in examples/*.mod; do
 	M1=${mod/examples/test}
 	M2=${M1/.mod/.cinter4}
 	M3=${M1/.mod/.out}
-	convert/CinterConvert.py $mod $M2 >$M3
+	$CONVERT $mod $M2 >$M3
 	echo `grep "[ ]errors[.]" $M3` `md5sum $M2`
 done